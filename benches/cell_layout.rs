@@ -0,0 +1,108 @@
+//! Compares the packed `Cell` grid against the old one-byte-per-cell grid
+//!
+//! Run with `cargo bench --bench cell_layout`
+
+use std::hint::black_box;
+use std::mem::size_of;
+use std::time::{Duration, Instant};
+
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+use sandsim::cell::{Cell, MOVED};
+use sandsim::World;
+
+
+const WIDTH: usize = 512;
+const HEIGHT: usize = 512;
+const TICKS: u32 = 50;
+
+// The previous layout: material id and moved flag packed into one byte
+const MOVED_FLAG: u8 = 0b1000_0000;
+const MATERIAL_MASK: u8 = 0b0111_1111;
+
+
+/// Sand falling straight down on the byte grid
+fn fall_bytes(grid: &mut [u8]) {
+    for y in (0..HEIGHT - 1).rev() {
+        for x in 0..WIDTH {
+            let idx = x + y * WIDTH;
+            if grid[idx] & MOVED_FLAG != 0 || grid[idx] & MATERIAL_MASK != 1 {
+                continue;
+            }
+            let below = idx + WIDTH;
+            if grid[below] & MATERIAL_MASK == 0 {
+                grid[below] = 1 | MOVED_FLAG;
+                grid[idx] = 0;
+            }
+        }
+    }
+    for cell in grid.iter_mut() {
+        *cell &= MATERIAL_MASK;
+    }
+}
+
+/// The same rule on the packed cell grid
+fn fall_cells(grid: &mut [Cell]) {
+    for y in (0..HEIGHT - 1).rev() {
+        for x in 0..WIDTH {
+            let idx = x + y * WIDTH;
+            if grid[idx].moved() || grid[idx].material != 1 {
+                continue;
+            }
+            let below = idx + WIDTH;
+            if grid[below].material == 0 {
+                grid.swap(idx, below);
+                grid[below].flags |= MOVED;
+            }
+        }
+    }
+    for cell in grid.iter_mut() {
+        cell.flags &= !MOVED;
+    }
+}
+
+fn time(name: &str, cells: usize, mut tick: impl FnMut()) -> Duration {
+    let start = Instant::now();
+    for _ in 0..TICKS {
+        tick();
+    }
+    let elapsed = start.elapsed();
+    println!(
+        "{name:<24} {:>8.2} ms/tick {:>8.2} ns/cell",
+        elapsed.as_secs_f64() * 1000.0 / TICKS as f64,
+        elapsed.as_nanos() as f64 / (TICKS as f64 * cells as f64),
+    );
+    elapsed
+}
+
+fn main() {
+    let cells = WIDTH * HEIGHT;
+    let mut rng = StdRng::seed_from_u64(1);
+    let sand: Vec<bool> = (0..cells).map(|_| rng.gen_bool(0.3)).collect();
+
+    println!("grid {WIDTH}x{HEIGHT}, {TICKS} ticks");
+    println!(
+        "memory: byte grid {} B/cell ({} KiB with temperature), Cell grid {} B/cell ({} KiB)",
+        size_of::<u8>(),
+        cells * (size_of::<u8>() + size_of::<f32>()) / 1024,
+        size_of::<Cell>(),
+        cells * size_of::<Cell>() / 1024,
+    );
+
+    let mut bytes: Vec<u8> = sand.iter().map(|&s| s as u8).collect();
+    let bytes_time = time("fall, byte grid", cells, || fall_bytes(black_box(&mut bytes)));
+
+    let mut packed: Vec<Cell> = sand.iter().map(|&s| Cell::new(s as u8, 20.0, 0)).collect();
+    let cells_time = time("fall, Cell grid", cells, || fall_cells(black_box(&mut packed)));
+
+    println!("Cell grid is {:.2}x the byte grid time", cells_time.as_secs_f64() / bytes_time.as_secs_f64());
+
+    let mut world = World::new(WIDTH, HEIGHT, 1);
+    let sand_id = world.materials().id("sand").expect("built-in sand");
+    for (i, &s) in sand.iter().enumerate() {
+        if s {
+            world.set(i % WIDTH, i / WIDTH, sand_id);
+        }
+    }
+    time("World::step", cells, || world.step());
+}
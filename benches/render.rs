@@ -0,0 +1,71 @@
+//! Compares drawing the grid with one `fill_rect` per cell against uploading it
+//! to a streaming texture, both on SDL's software renderer
+//!
+//! Run with `cargo bench --bench render`
+
+use std::time::{Duration, Instant};
+
+use sandsim::canvas::{fill_cells, GridTexture};
+use sandsim::World;
+use sdl3::pixels::{Color, PixelFormat};
+use sdl3::render::FRect;
+use sdl3::surface::Surface;
+
+
+const SCREEN_W: usize = 800;
+const SCREEN_H: usize = 600;
+const FRAMES: u32 = 20;
+
+
+fn time(name: &str, mut frame: impl FnMut()) -> Duration {
+    let start = Instant::now();
+    for _ in 0..FRAMES {
+        frame();
+    }
+    let elapsed = start.elapsed();
+    println!("{name:<32} {:>8.2} ms/frame", elapsed.as_secs_f64() * 1000.0 / FRAMES as f64);
+    elapsed
+}
+
+fn main() {
+    let mut canvas = Surface::new(SCREEN_W as u32, SCREEN_H as u32, PixelFormat::RGBA32)
+        .and_then(|surface| surface.into_canvas())
+        .expect("software renderer");
+    let texture_creator = canvas.texture_creator();
+
+    println!("{SCREEN_W}x{SCREEN_H} software canvas, {FRAMES} frames");
+    for square_size in [10, 4, 2, 1] {
+        let width = SCREEN_W / square_size;
+        let height = SCREEN_H / square_size;
+
+        // A half-filled world so the pixels are not all the same color
+        let mut world = World::new(width, height, 1);
+        let sand = world.materials().id("sand").expect("built-in sand");
+        for y in height / 2..height {
+            for x in 0..width {
+                world.set(x, y, sand);
+            }
+        }
+        let mut pixels = vec![0u8; width * height * 4];
+        world.render(&mut pixels);
+
+        println!("grid {width}x{height}, square size {square_size}");
+        let rects_time = time("  fill_rect per cell", || {
+            canvas.set_draw_color(Color::RGB(0, 0, 0));
+            canvas.clear();
+            fill_cells(&mut canvas, &pixels, width, height, square_size as i32);
+            canvas.present();
+        });
+
+        let mut texture = GridTexture::new(&texture_creator, width, height).expect("streaming texture");
+        let screen = FRect::new(0.0, 0.0, SCREEN_W as f32, SCREEN_H as f32);
+        let texture_time = time("  streaming texture", || {
+            canvas.set_draw_color(Color::RGB(0, 0, 0));
+            canvas.clear();
+            texture.draw(&mut canvas, &pixels, screen);
+            canvas.present();
+        });
+
+        println!("  texture is {:.1}x faster", rects_time.as_secs_f64() / texture_time.as_secs_f64());
+    }
+}
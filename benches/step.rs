@@ -0,0 +1,73 @@
+//! Times `World::step` on a large grid with different numbers of threads
+//!
+//! Run with `cargo bench --bench step`
+
+use std::thread::available_parallelism;
+use std::time::Instant;
+
+use rayon::ThreadPoolBuilder;
+use sandsim::World;
+
+
+const WIDTH: usize = 1024;
+const HEIGHT: usize = 768;
+const TICKS: u32 = 30;
+
+
+/// Bands of powders, liquids and gases over a stone floor, so every kind of
+/// update is busy
+fn busy_world() -> World {
+    let mut world = World::new(WIDTH, HEIGHT, 1);
+    let id = |name: &str| world.materials().id(name).expect("built-in material");
+    let bands = [id("sand"), id("water"), id("oil"), id("lava"), id("steam"), id("gunpowder")];
+    let stone = id("stone");
+
+    for y in 0..HEIGHT {
+        for x in 0..WIDTH {
+            let material = if y > HEIGHT - 8 {
+                stone
+            } else if (x / 16 + y / 16) % 3 == 0 {
+                bands[(x / 96 + y / 128) % bands.len()]
+            } else {
+                continue;
+            };
+            world.set(x, y, material);
+        }
+    }
+    world
+}
+
+fn main() {
+    let start = busy_world();
+    let cores = available_parallelism().map_or(1, |cores| cores.get());
+    let mut thread_counts = vec![1, 2, 4, cores];
+    thread_counts.sort_unstable();
+    thread_counts.dedup();
+
+    println!("{WIDTH}x{HEIGHT} grid, {TICKS} ticks, {cores} cores");
+    let mut single = None;
+    let mut checksum = None;
+    for threads in thread_counts {
+        let pool = ThreadPoolBuilder::new().num_threads(threads).build().expect("thread pool");
+        let mut world = start.clone();
+
+        let begin = Instant::now();
+        pool.install(|| {
+            for _ in 0..TICKS {
+                world.step();
+            }
+        });
+        let elapsed = begin.elapsed().as_secs_f64();
+
+        let single = *single.get_or_insert(elapsed);
+        println!(
+            "{threads:>3} threads {:>8.2} ms/tick   {:.2}x",
+            elapsed * 1000.0 / TICKS as f64,
+            single / elapsed,
+        );
+
+        // Every chunk has its own random stream, so threads never change the outcome
+        let expected = *checksum.get_or_insert(world.checksum());
+        assert_eq!(world.checksum(), expected, "{threads} threads simulated a different world");
+    }
+}
@@ -0,0 +1,236 @@
+use std::collections::HashSet;
+
+use sdl3::pixels::{Color, PixelFormat};
+use sdl3::rect::Rect;
+use sdl3::render::{Canvas, FPoint, FRect, RenderTarget, ScaleMode, Texture, TextureCreator, TextureValueError};
+
+
+/// Draws RGBA `pixels` of a `width` x `height` grid as one filled square per cell
+///
+/// This costs a draw call per cell; [`GridTexture`] is the fast path, this one is
+/// kept to compare against
+pub fn fill_cells<T: RenderTarget>(canvas: &mut Canvas<T>, pixels: &[u8], width: usize, height: usize, square_size: i32) {
+    for y in 0..height {
+        for x in 0..width {
+            let p = (x + y * width) * 4;
+            canvas.set_draw_color(Color::RGB(pixels[p], pixels[p + 1], pixels[p + 2]));
+            let square = Rect::new(
+                x as i32 * square_size,
+                y as i32 * square_size,
+                square_size as u32,
+                square_size as u32,
+            );
+            let _ = canvas.fill_rect(square);
+        }
+    }
+}
+
+
+/// A streaming texture holding one texel per cell, uploaded once per frame and
+/// stretched over the screen in a single copy
+pub struct GridTexture<'r> {
+    texture: Texture<'r>,
+    width: usize,
+    height: usize,
+}
+
+impl<'r> GridTexture<'r> {
+    pub fn new<C>(creator: &'r TextureCreator<C>, width: usize, height: usize) -> Result<GridTexture<'r>, TextureValueError> {
+        // RGBA32 matches the byte order `World::render` writes on every platform
+        let mut texture = creator.create_texture_streaming(PixelFormat::RGBA32, width as u32, height as u32)?;
+        // Cells stay sharp squares instead of being blurred together
+        texture.set_scale_mode(ScaleMode::Nearest);
+
+        Ok(GridTexture { texture, width, height })
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Uploads RGBA `pixels` and draws them stretched over `dst`
+    pub fn draw<T: RenderTarget>(&mut self, canvas: &mut Canvas<T>, pixels: &[u8], dst: FRect) {
+        let _ = self.texture.update(None, pixels, self.width * 4);
+        let _ = canvas.copy(&self.texture, None, dst);
+    }
+
+    /// Uploads only the cells inside `cells` from RGBA `pixels`, which are laid
+    /// out for the whole grid
+    pub fn update(&mut self, pixels: &[u8], cells: Rect) {
+        let start = (cells.x() as usize + cells.y() as usize * self.width) * 4;
+        let _ = self.texture.update(cells, &pixels[start..], self.width * 4);
+    }
+
+    /// Draws the cells inside `src`, or the whole grid, stretched over `dst`
+    /// without uploading anything
+    pub fn copy<T: RenderTarget>(&self, canvas: &mut Canvas<T>, src: Option<FRect>, dst: FRect) {
+        let _ = canvas.copy(&self.texture, src, dst);
+    }
+}
+
+
+/// Where the grid lands on screen for one frame
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Viewport {
+    /// Top left corner of the grid, in screen pixels; off screen when zoomed in
+    pub x: f32,
+    pub y: f32,
+    /// Screen pixels per cell
+    pub scale: f32,
+    grid_w: usize,
+    grid_h: usize,
+}
+
+impl Viewport {
+    /// Fits a `grid_w` x `grid_h` grid into a `screen_w` x `screen_h` screen: scaled
+    /// uniformly and centered, leaving bars along the sides that do not fill up
+    pub fn fit(screen_w: f32, screen_h: f32, grid_w: usize, grid_h: usize) -> Viewport {
+        let scale = (screen_w / grid_w as f32).min(screen_h / grid_h as f32);
+        Viewport {
+            x: (screen_w - grid_w as f32 * scale) / 2.0,
+            y: (screen_h - grid_h as f32 * scale) / 2.0,
+            scale,
+            grid_w,
+            grid_h,
+        }
+    }
+
+    /// The screen area covered by the grid
+    pub fn rect(&self) -> FRect {
+        FRect::new(self.x, self.y, self.grid_w as f32 * self.scale, self.grid_h as f32 * self.scale)
+    }
+
+    /// The cells that show on a `screen_w` x `screen_h` screen, and the screen
+    /// area they cover, or `None` when the grid is entirely off screen
+    pub fn visible(&self, screen_w: f32, screen_h: f32) -> Option<(Rect, FRect)> {
+        let first_x = (-self.x / self.scale).floor().max(0.0) as usize;
+        let first_y = (-self.y / self.scale).floor().max(0.0) as usize;
+        let end_x = (((screen_w - self.x) / self.scale).ceil().max(0.0) as usize).min(self.grid_w);
+        let end_y = (((screen_h - self.y) / self.scale).ceil().max(0.0) as usize).min(self.grid_h);
+        if first_x >= end_x || first_y >= end_y {
+            return None;
+        }
+
+        let cells = Rect::new(first_x as i32, first_y as i32, (end_x - first_x) as u32, (end_y - first_y) as u32);
+        let screen = FRect::new(
+            self.x + first_x as f32 * self.scale,
+            self.y + first_y as f32 * self.scale,
+            (end_x - first_x) as f32 * self.scale,
+            (end_y - first_y) as f32 * self.scale,
+        );
+        Some((cells, screen))
+    }
+
+    /// The cell under screen position (`x`, `y`), if it is on the grid
+    pub fn cell_at(&self, x: f32, y: f32) -> Option<(usize, usize)> {
+        let cell_x = ((x - self.x) / self.scale).floor();
+        let cell_y = ((y - self.y) / self.scale).floor();
+        if cell_x < 0.0 || cell_y < 0.0 || cell_x >= self.grid_w as f32 || cell_y >= self.grid_h as f32 {
+            return None;
+        }
+        Some((cell_x as usize, cell_y as usize))
+    }
+
+    /// The cell under screen position (`x`, `y`), or the closest one when it is off the grid
+    pub fn nearest_cell(&self, x: f32, y: f32) -> (usize, usize) {
+        let cell_x = ((x - self.x) / self.scale).floor().clamp(0.0, self.grid_w as f32 - 1.0);
+        let cell_y = ((y - self.y) / self.scale).floor().clamp(0.0, self.grid_h as f32 - 1.0);
+        (cell_x as usize, cell_y as usize)
+    }
+
+    /// Draws the border of the area covered by `cells` in the current draw color
+    pub fn outline<T: RenderTarget>(&self, canvas: &mut Canvas<T>, cells: &[(usize, usize)]) {
+        let covered: HashSet<(usize, usize)> = cells.iter().copied().collect();
+        let corner = |x: usize, y: usize| FPoint::new(self.x + x as f32 * self.scale, self.y + y as f32 * self.scale);
+
+        for &(x, y) in &covered {
+            // Only edges between a covered and an uncovered cell are part of the border
+            if x == 0 || !covered.contains(&(x - 1, y)) {
+                let _ = canvas.draw_line(corner(x, y), corner(x, y + 1));
+            }
+            if !covered.contains(&(x + 1, y)) {
+                let _ = canvas.draw_line(corner(x + 1, y), corner(x + 1, y + 1));
+            }
+            if y == 0 || !covered.contains(&(x, y - 1)) {
+                let _ = canvas.draw_line(corner(x, y), corner(x + 1, y));
+            }
+            if !covered.contains(&(x, y + 1)) {
+                let _ = canvas.draw_line(corner(x, y + 1), corner(x + 1, y + 1));
+            }
+        }
+    }
+}
+
+
+/// A view into the grid that can zoom in on and pan around worlds larger than the window
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Camera {
+    /// Grid position shown in the middle of the screen, in cells
+    pub center_x: f32,
+    pub center_y: f32,
+    /// Screen pixels per cell
+    pub zoom: f32,
+}
+
+impl Camera {
+    /// Closest zoom, in screen pixels per cell
+    pub const MAX_ZOOM: f32 = 64.0;
+
+    /// A camera looking at the middle of a `grid_w` x `grid_h` grid
+    pub fn new(grid_w: usize, grid_h: usize, zoom: f32) -> Camera {
+        Camera {
+            center_x: grid_w as f32 / 2.0,
+            center_y: grid_h as f32 / 2.0,
+            zoom,
+        }
+    }
+
+    /// Moves the view by (`dx`, `dy`) screen pixels, the way dragging the grid would
+    pub fn pan(&mut self, dx: f32, dy: f32) {
+        self.center_x -= dx / self.zoom;
+        self.center_y -= dy / self.zoom;
+    }
+
+    /// Multiplies the zoom by `factor`, keeping the cell under screen position
+    /// (`x`, `y`) where it is
+    pub fn zoom_at(&mut self, screen_w: f32, screen_h: f32, x: f32, y: f32, factor: f32) {
+        let cell_x = self.center_x + (x - screen_w / 2.0) / self.zoom;
+        let cell_y = self.center_y + (y - screen_h / 2.0) / self.zoom;
+        self.zoom = (self.zoom * factor).min(Camera::MAX_ZOOM);
+        self.center_x = cell_x - (x - screen_w / 2.0) / self.zoom;
+        self.center_y = cell_y - (y - screen_h / 2.0) / self.zoom;
+    }
+
+    /// Keeps the view on the grid: never zoomed out further than the whole grid,
+    /// never panned past its edges, and centered along sides it does not fill
+    pub fn clamp(&mut self, screen_w: f32, screen_h: f32, grid_w: usize, grid_h: usize) {
+        let fit = (screen_w / grid_w as f32).min(screen_h / grid_h as f32);
+        self.zoom = self.zoom.clamp(fit.min(Camera::MAX_ZOOM), Camera::MAX_ZOOM);
+
+        let clamp_axis = |center: f32, screen: f32, grid: usize| {
+            let half = screen / 2.0 / self.zoom;
+            if grid as f32 <= half * 2.0 {
+                grid as f32 / 2.0
+            } else {
+                center.clamp(half, grid as f32 - half)
+            }
+        };
+        self.center_x = clamp_axis(self.center_x, screen_w, grid_w);
+        self.center_y = clamp_axis(self.center_y, screen_h, grid_h);
+    }
+
+    /// Where a `grid_w` x `grid_h` grid lands on a `screen_w` x `screen_h` screen
+    pub fn viewport(&self, screen_w: f32, screen_h: f32, grid_w: usize, grid_h: usize) -> Viewport {
+        Viewport {
+            x: screen_w / 2.0 - self.center_x * self.zoom,
+            y: screen_h / 2.0 - self.center_y * self.zoom,
+            scale: self.zoom,
+            grid_w,
+            grid_h,
+        }
+    }
+}
@@ -0,0 +1,53 @@
+use crate::material::{MaterialId, AIR, AMBIENT_TEMPERATURE};
+
+
+/// Set on a cell once it has moved during the current tick, so it is not updated twice
+pub const MOVED: u8 = 0b0000_0001;
+/// Set on a flammable cell while it burns; its `life` counts down the remaining burn time
+pub const BURNING: u8 = 0b0000_0010;
+
+
+/// One grid cell: its material plus per-cell state that moves along with it
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Cell {
+    pub material: MaterialId,
+    /// Bit flags such as [`MOVED`] and [`BURNING`]
+    pub flags: u8,
+    /// Random per-cell value used to vary the color of grainy materials
+    pub color_seed: u8,
+    /// Ticks this cell has left to live, for materials that burn out or dissipate
+    pub life: u16,
+    /// Temperature in degrees
+    pub temperature: f32,
+    /// Cells per tick in x and y; reserved for momentum, nothing moves by it yet
+    /// but saves already keep it
+    pub velocity: (i8, i8),
+}
+
+impl Cell {
+    pub const fn new(material: MaterialId, temperature: f32, color_seed: u8) -> Cell {
+        Cell {
+            material,
+            flags: 0,
+            color_seed,
+            life: 0,
+            temperature,
+            velocity: (0, 0),
+        }
+    }
+
+    pub fn moved(&self) -> bool {
+        self.flags & MOVED != 0
+    }
+
+    pub fn burning(&self) -> bool {
+        self.flags & BURNING != 0
+    }
+}
+
+impl Default for Cell {
+    /// Air at ambient temperature
+    fn default() -> Cell {
+        Cell::new(AIR, AMBIENT_TEMPERATURE, 0)
+    }
+}
@@ -0,0 +1,430 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+
+use crate::material::{
+    Behavior, Burn, Lifetime, Material, MaterialId, MaterialRegistry, Shading, State, Transition, AIR,
+    AMBIENT_TEMPERATURE,
+};
+use crate::reaction::{Neighborhood, Reaction};
+use crate::world::MAX_REACH;
+
+
+/// Everything that can go wrong while reading a materials file
+#[derive(Debug)]
+pub enum ConfigError {
+    /// The file could not be read
+    Io { path: PathBuf, source: std::io::Error },
+    /// The file is not valid TOML or does not match the expected layout
+    Parse { path: Option<PathBuf>, source: toml::de::Error },
+    /// The file parsed but describes something impossible
+    Invalid { path: Option<PathBuf>, message: String },
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let location = |path: &Option<PathBuf>| match path {
+            Some(path) => path.display().to_string(),
+            None => "materials".to_string(),
+        };
+
+        match self {
+            ConfigError::Io { path, source } => write!(f, "{}: {}", path.display(), source),
+            ConfigError::Parse { path, source } => write!(f, "{}: {}", location(path), source),
+            ConfigError::Invalid { path, message } => write!(f, "{}: {}", location(path), message),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ConfigError::Io { source, .. } => Some(source),
+            ConfigError::Parse { source, .. } => Some(source),
+            ConfigError::Invalid { .. } => None,
+        }
+    }
+}
+
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct MaterialsFile {
+    /// 4 or 8, the neighbors every reaction looks at
+    neighborhood: Option<u8>,
+    #[serde(default, rename = "material")]
+    materials: Vec<MaterialDef>,
+    #[serde(default, rename = "reaction")]
+    reactions: Vec<ReactionDef>,
+}
+
+#[derive(Deserialize, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+enum ShadingKind {
+    #[default]
+    Flat,
+    Grainy,
+    Textured,
+    Liquid,
+    Cracked,
+    Fire,
+    Fade,
+}
+
+#[derive(Deserialize, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+enum BehaviorKind {
+    #[default]
+    None,
+    Explode,
+    Fly,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct MaterialDef {
+    name: String,
+    color: [u8; 3],
+    image_color: Option<[u8; 3]>,
+    density: f32,
+    state: State,
+    #[serde(default)]
+    shading: ShadingKind,
+    surface: Option<[u8; 3]>,
+    crack: Option<[u8; 3]>,
+    tip: Option<[u8; 3]>,
+    #[serde(default)]
+    behavior: BehaviorKind,
+    radius: Option<i32>,
+    into: Option<String>,
+    temperature: Option<f32>,
+    conductivity: Option<f32>,
+    above: Option<TransitionDef>,
+    below: Option<TransitionDef>,
+    lifetime: Option<LifetimeDef>,
+    #[serde(default)]
+    ignites: bool,
+    burn: Option<BurnDef>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TransitionDef {
+    at: f32,
+    into: String,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct LifetimeDef {
+    min: u16,
+    max: u16,
+    into: String,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct BurnDef {
+    chance: f32,
+    duration: u16,
+    leaves: String,
+    emits: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ReactionDef {
+    between: [String; 2],
+    becomes: [String; 2],
+    probability: Option<f32>,
+}
+
+
+/// Reads a materials file from disk
+pub fn load_materials(path: &Path) -> Result<MaterialRegistry, ConfigError> {
+    let text = fs::read_to_string(path).map_err(|source| ConfigError::Io {
+        path: path.to_path_buf(),
+        source,
+    })?;
+
+    parse_materials(&text).map_err(|err| match err {
+        ConfigError::Parse { source, .. } => ConfigError::Parse { path: Some(path.to_path_buf()), source },
+        ConfigError::Invalid { message, .. } => ConfigError::Invalid { path: Some(path.to_path_buf()), message },
+        err => err,
+    })
+}
+
+/// Builds a material registry from the text of a materials file
+pub fn parse_materials(text: &str) -> Result<MaterialRegistry, ConfigError> {
+    let file: MaterialsFile = toml::from_str(text).map_err(|source| ConfigError::Parse { path: None, source })?;
+    let invalid = |message: String| ConfigError::Invalid { path: None, message };
+
+    if file.materials.is_empty() {
+        return Err(invalid("no materials defined, at least one `[[material]]` is needed".to_string()));
+    }
+    if file.materials.len() > MaterialId::MAX as usize {
+        return Err(invalid(format!("{} materials defined, at most 255 are supported", file.materials.len())));
+    }
+
+    // Names are resolved up front so materials can refer to ones defined after them
+    let mut ids: HashMap<&str, MaterialId> = HashMap::new();
+    ids.insert("air", AIR);
+    for (i, def) in file.materials.iter().enumerate() {
+        if def.name.is_empty() {
+            return Err(invalid(format!("material #{} has an empty name", i + 1)));
+        }
+        if ids.insert(def.name.as_str(), (i + 1) as MaterialId).is_some() {
+            return Err(invalid(format!("material \"{}\" is defined more than once", def.name)));
+        }
+    }
+
+    let lookup = |name: &str, context: &str| -> Result<MaterialId, ConfigError> {
+        ids.get(name)
+            .copied()
+            .ok_or_else(|| invalid(format!("{context} refers to unknown material \"{name}\"")))
+    };
+
+    let mut registry = MaterialRegistry::empty();
+
+    for def in &file.materials {
+        let context = format!("material \"{}\"", def.name);
+
+        let shading = match def.shading {
+            ShadingKind::Flat => Shading::Flat,
+            ShadingKind::Grainy => Shading::Grainy,
+            ShadingKind::Textured => Shading::Textured,
+            ShadingKind::Liquid => {
+                let [r, g, b] = def.surface
+                    .ok_or_else(|| invalid(format!("{context} uses \"liquid\" shading but has no `surface` color")))?;
+                Shading::Liquid { surface: (r, g, b) }
+            }
+            ShadingKind::Cracked => {
+                let [r, g, b] = def.crack
+                    .ok_or_else(|| invalid(format!("{context} uses \"cracked\" shading but has no `crack` color")))?;
+                Shading::Cracked { crack: (r, g, b) }
+            }
+            ShadingKind::Fire => {
+                let [r, g, b] = def.tip
+                    .ok_or_else(|| invalid(format!("{context} uses \"fire\" shading but has no `tip` color")))?;
+                Shading::Fire { tip: (r, g, b) }
+            }
+            ShadingKind::Fade => {
+                if def.lifetime.is_none() {
+                    return Err(invalid(format!("{context} uses \"fade\" shading but has no `lifetime`")));
+                }
+                Shading::Fade
+            }
+        };
+
+        let behavior = match def.behavior {
+            BehaviorKind::None => Behavior::None,
+            BehaviorKind::Explode => {
+                let radius = def.radius
+                    .ok_or_else(|| invalid(format!("{context} explodes but has no `radius`")))?;
+                if radius < 0 {
+                    return Err(invalid(format!("{context} has a negative explosion radius")));
+                }
+                if radius as usize > MAX_REACH {
+                    return Err(invalid(format!("{context} has an explosion radius over {MAX_REACH}")));
+                }
+                Behavior::Explode { radius }
+            }
+            BehaviorKind::Fly => {
+                let into = def.into.as_deref()
+                    .ok_or_else(|| invalid(format!("{context} flies but has no `into` material")))?;
+                Behavior::Fly { into: lookup(into, &context)? }
+            }
+        };
+
+        let conductivity = def.conductivity.unwrap_or(0.1);
+        if !(0.0..=1.0).contains(&conductivity) {
+            return Err(invalid(format!("{context} has conductivity {conductivity}, expected a value in [0, 1]")));
+        }
+
+        let transition = |def: &Option<TransitionDef>| -> Result<Option<Transition>, ConfigError> {
+            def.as_ref()
+                .map(|t| Ok(Transition { at: t.at, into: lookup(&t.into, &context)? }))
+                .transpose()
+        };
+        let above = transition(&def.above)?;
+        let below = transition(&def.below)?;
+        if let (Some(above), Some(below)) = (above, below)
+            && below.at >= above.at {
+            return Err(invalid(format!("{context} has its `below` threshold above its `above` threshold")));
+        }
+
+        let lifetime = match &def.lifetime {
+            Some(lifetime) if lifetime.min == 0 || lifetime.min > lifetime.max => {
+                return Err(invalid(format!("{context} needs a lifetime with 1 <= min <= max")));
+            }
+            Some(lifetime) => Some(Lifetime { min: lifetime.min, max: lifetime.max, into: lookup(&lifetime.into, &context)? }),
+            None => None,
+        };
+
+        let burn = match &def.burn {
+            // Both count down the cell's `life`
+            Some(_) if def.lifetime.is_some() => {
+                return Err(invalid(format!("{context} has both a `lifetime` and a `burn`, which cannot be combined")));
+            }
+            Some(burn) if !(0.0..=1.0).contains(&burn.chance) => {
+                return Err(invalid(format!("{context} has burn chance {}, expected a value in [0, 1]", burn.chance)));
+            }
+            Some(burn) if burn.duration == 0 => {
+                return Err(invalid(format!("{context} needs a burn duration of at least 1 tick")));
+            }
+            Some(burn) => Some(Burn {
+                chance: burn.chance,
+                duration: burn.duration,
+                leaves: lookup(&burn.leaves, &context)?,
+                emits: burn.emits.as_deref().map(|name| lookup(name, &context)).transpose()?,
+            }),
+            None => None,
+        };
+
+        let [r, g, b] = def.color;
+        let mut material = Material::new(&def.name, (r, g, b), def.density, def.state)
+            .shading(shading)
+            .behavior(behavior)
+            .temperature(def.temperature.unwrap_or(AMBIENT_TEMPERATURE))
+            .conductivity(conductivity)
+            .ignites(def.ignites);
+        material.image_color = def.image_color.map(|[r, g, b]| (r, g, b));
+        material.above = above;
+        material.below = below;
+        material.lifetime = lifetime;
+        material.burn = burn;
+        registry.add(material);
+    }
+
+    let neighborhood = match file.neighborhood {
+        None | Some(4) => Neighborhood::VonNeumann,
+        Some(8) => Neighborhood::Moore,
+        Some(n) => return Err(invalid(format!("neighborhood must be 4 or 8, not {n}"))),
+    };
+    registry.reactions_mut().set_neighborhood(neighborhood);
+
+    for (i, def) in file.reactions.iter().enumerate() {
+        let context = format!("reaction #{}", i + 1);
+
+        let probability = def.probability.unwrap_or(1.0);
+        if !(probability > 0.0 && probability <= 1.0) {
+            return Err(invalid(format!("{context} has probability {probability}, expected a value in (0, 1]")));
+        }
+
+        let reaction = Reaction {
+            a: lookup(&def.between[0], &context)?,
+            b: lookup(&def.between[1], &context)?,
+            a_becomes: lookup(&def.becomes[0], &context)?,
+            b_becomes: lookup(&def.becomes[1], &context)?,
+            probability,
+        };
+        if registry.reactions_mut().add(reaction).is_err() {
+            return Err(invalid(format!(
+                "{context}: \"{}\" and \"{}\" already react in an earlier reaction",
+                def.between[0], def.between[1],
+            )));
+        }
+    }
+
+    Ok(registry)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAND: &str = r#"
+[[material]]
+name = "sand"
+color = [200, 180, 100]
+density = 1.5
+state = "powder"
+"#;
+
+    /// The message `parse_materials` fails with on `text`
+    fn error(text: &str) -> String {
+        match parse_materials(text) {
+            Ok(_) => panic!("parsed a broken materials file:\n{text}"),
+            Err(err) => err.to_string(),
+        }
+    }
+
+    #[test]
+    fn parses_materials_and_reactions() {
+        let text = format!("neighborhood = 8\n{SAND}\n{}", r#"
+[[material]]
+name = "glass"
+color = [220, 230, 240]
+density = 3
+state = "solid"
+
+[[reaction]]
+between = ["sand", "glass"]
+becomes = ["glass", "glass"]
+probability = 0.5
+"#);
+        let materials = parse_materials(&text).unwrap();
+        let (sand, glass) = (materials.id("sand").unwrap(), materials.id("glass").unwrap());
+        assert_eq!(materials.len(), 3);
+        assert_eq!(materials.reactions().neighborhood(), Neighborhood::Moore);
+        assert_eq!(materials.reactions().lookup(glass, sand).unwrap().probability, 0.5);
+    }
+
+    #[test]
+    fn rejects_an_empty_file() {
+        assert!(error("").contains("no materials defined"));
+    }
+
+    #[test]
+    fn rejects_unknown_materials() {
+        let text = format!("{SAND}above = {{ at = 600, into = \"glass\" }}\n");
+        assert_eq!(error(&text), "materials: material \"sand\" refers to unknown material \"glass\"");
+    }
+
+    #[test]
+    fn rejects_duplicate_names() {
+        assert_eq!(error(&format!("{SAND}{SAND}")), "materials: material \"sand\" is defined more than once");
+    }
+
+    #[test]
+    fn rejects_liquid_shading_without_a_surface() {
+        let text = SAND.replace("state = \"powder\"", "state = \"liquid\"\nshading = \"liquid\"");
+        assert!(error(&text).contains("uses \"liquid\" shading but has no `surface` color"));
+    }
+
+    #[test]
+    fn rejects_bad_neighborhoods() {
+        assert_eq!(error(&format!("neighborhood = 6\n{SAND}")), "materials: neighborhood must be 4 or 8, not 6");
+    }
+
+    #[test]
+    fn rejects_explosions_out_of_reach() {
+        let text = format!("{SAND}behavior = \"explode\"\nradius = {}\n", MAX_REACH + 1);
+        assert!(error(&text).contains(&format!("explosion radius over {MAX_REACH}")));
+        let text = format!("{SAND}behavior = \"explode\"\nradius = {MAX_REACH}\n");
+        assert!(parse_materials(&text).is_ok());
+    }
+
+    #[test]
+    fn rejects_burning_materials_with_a_lifetime() {
+        let text = format!(
+            "{SAND}lifetime = {{ min = 5, max = 10, into = \"air\" }}\n\
+             burn = {{ chance = 0.5, duration = 20, leaves = \"air\" }}\n"
+        );
+        assert!(error(&text).contains("has both a `lifetime` and a `burn`"));
+    }
+
+    #[test]
+    fn rejects_unknown_keys() {
+        assert!(matches!(parse_materials(&format!("{SAND}colour = 3\n")), Err(ConfigError::Parse { .. })));
+    }
+
+    #[test]
+    fn built_in_materials_parse() {
+        assert!(parse_materials(include_str!("../materials.toml")).is_ok());
+    }
+}
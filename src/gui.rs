@@ -0,0 +1,828 @@
+use rand::Rng;
+use sandsim::{load_png, save_png, shape, Brush, ColorMatch, Edit, FixedTimestep, GifRecorder, History, RateCounter, Replay, Shape, Timeline, World};
+use sandsim::material::AIR;
+use sandsim::world::MAX_CELLS;
+use sdl3::pixels::Color;
+use sdl3::event::Event;
+use sdl3::keyboard::{Keycode, Mod, Scancode};
+use sdl3::rect::Rect;
+use sdl3::render::FRect;
+use sandsim::canvas::{Camera, GridTexture, Viewport};
+use sdl3::mouse::MouseButton;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use std::thread::sleep;
+
+
+/// File written by F5 and read back by F9
+const SAVE_PATH: &str = "world.sav";
+
+/// Largest window opened at startup; bigger worlds are explored with the camera
+const MAX_WINDOW_W: u32 = 1280;
+const MAX_WINDOW_H: u32 = 800;
+
+/// Zoom change per notch of the mouse wheel
+const ZOOM_STEP: f32 = 1.25;
+
+/// Side of the square the minimap fits in, and its distance from the window corner, in screen pixels
+const MINIMAP_SIZE: f32 = 160.0;
+const MINIMAP_MARGIN: f32 = 10.0;
+
+/// Off-screen cells, which only show on the minimap, are redrawn once every this many frames
+const MINIMAP_REFRESH: u64 = 15;
+
+/// Ticks between the full copies of the grid kept for rewinding
+const KEYFRAME_INTERVAL: u64 = 30;
+
+/// Ticks scrubbed per frame while the rewind key is held
+const REWIND_STEP: u64 = 2;
+
+/// How long the window has to keep its size before `--resize-world` follows it,
+/// so dragging a window edge does not rebuild the world every frame
+const RESIZE_DELAY: Duration = Duration::from_millis(250);
+
+/// Screenshots and recordings draw cells as large as fits this many pixels on
+/// the longer side, and never larger than `--cell-size`
+const EXPORT_SIZE: usize = 1024;
+
+
+/// What pressing a mouse button on the grid does
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Tool {
+    /// Paints with the brush for as long as the button is held
+    Brush,
+    /// Paints a line with the brush from where the button was pressed to where it is released
+    Line,
+    /// Fills a rectangle from where the button was pressed to where it is released
+    Rect,
+    /// Fills the connected area of the material that was clicked
+    Fill,
+}
+
+impl Tool {
+    fn name(self) -> &'static str {
+        match self {
+            Tool::Brush => "brush",
+            Tool::Line => "line",
+            Tool::Rect => "rectangle",
+            Tool::Fill => "fill",
+        }
+    }
+}
+
+
+/// A file name like `sandsim-1700000000.png` for screenshots and recordings
+fn timestamped(extension: &str) -> PathBuf {
+    let seconds = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |time| time.as_secs());
+    PathBuf::from(format!("sandsim-{seconds}.{extension}"))
+}
+
+
+/// How many pixels across each cell of `world` is drawn in screenshots and recordings
+fn export_scale(world: &World, cell_size: u32) -> usize {
+    (EXPORT_SIZE / world.width().max(world.height())).clamp(1, cell_size.max(1) as usize)
+}
+
+/// Stops `replay`, if one is running, at the current state of `world` and writes it to `path`
+fn stop_replay(replay: &mut Option<Replay>, world: &World, path: Option<&Path>) {
+    if let (Some(mut replay), Some(path)) = (replay.take(), path) {
+        replay.finish(world);
+        match replay.save(path) {
+            Ok(()) => println!("saved replay of {} ticks to {}", world.tick(), path.display()),
+            Err(err) => eprintln!("error: {err}"),
+        }
+    }
+}
+
+/// Applies `edit` to `world`, adding it to the undo `history` and to the `replay`
+/// being recorded, if any
+fn paint(world: &mut World, replay: &mut Option<Replay>, history: &mut History, edit: Edit) {
+    history.paint(world, &edit);
+    if let Some(replay) = replay {
+        replay.record(world, edit);
+    }
+}
+
+/// Draws the `cells` of `world` into `pixels`, tinted by temperature with `heat_view`
+fn render_cells(world: &World, pixels: &mut [u8], heat_view: bool, cells: Rect) {
+    let (x, y) = (cells.x() as usize, cells.y() as usize);
+    let (width, height) = (cells.width() as usize, cells.height() as usize);
+    if heat_view {
+        world.render_heat_region(pixels, x, y, width, height);
+    } else {
+        world.render_region(pixels, x, y, width, height);
+    }
+}
+
+/// Where the whole grid is drawn small in the bottom right corner of the screen
+fn minimap(screen_w: f32, screen_h: f32, world: &World) -> Viewport {
+    let mut minimap = Viewport::fit(MINIMAP_SIZE, MINIMAP_SIZE, world.width(), world.height());
+    minimap.x += screen_w - MINIMAP_SIZE - MINIMAP_MARGIN;
+    minimap.y += screen_h - MINIMAP_SIZE - MINIMAP_MARGIN;
+    minimap
+}
+
+/// Stops the GIF `recording`, if one is running, and closes its file
+fn stop_recording(recording: &mut Option<GifRecorder>) {
+    if let Some(recorder) = recording.take() {
+        let (path, frames) = (recorder.path().to_path_buf(), recorder.frames());
+        match recorder.finish() {
+            Ok(()) => println!("saved {frames} frames to {}", path.display()),
+            Err(err) => eprintln!("error: {err}"),
+        }
+    }
+}
+
+
+/// Command line options of the windowed frontend
+struct Options {
+    /// Materials file given with `--materials`
+    materials: Option<PathBuf>,
+    /// Simulation seed given with `--seed`, random when missing
+    seed: Option<u64>,
+    /// Starting scene given with `--image`
+    image: Option<PathBuf>,
+    /// How `--image` pixels are matched to materials
+    color_match: ColorMatch,
+    /// Replay file given with `--record`, written on exit
+    record: Option<PathBuf>,
+    /// Simulation ticks per second at normal speed, `--tick-rate`
+    tick_rate: f64,
+    /// Frames drawn per second, `--fps`
+    fps: f64,
+    /// Grid size in cells, `--width` and `--height`
+    width: usize,
+    height: usize,
+    /// Window pixels per cell when the window opens, `--cell-size`
+    cell_size: u32,
+    /// Resize the world along with the window instead of scaling it, `--resize-world`
+    resize_world: bool,
+    /// Memory kept for undoing, in MiB, `--undo-memory`
+    undo_memory: usize,
+    /// Seconds of simulation kept for rewinding, `--rewind`
+    rewind: f64,
+    /// Threads updating the world, `--threads`, one per core when missing
+    threads: Option<usize>,
+}
+
+fn parse_args(args: &[String]) -> Result<Options, String> {
+    let mut options = Options {
+        materials: None,
+        seed: None,
+        image: None,
+        color_match: ColorMatch::Nearest,
+        record: None,
+        tick_rate: 30.0,
+        fps: 60.0,
+        width: 80,
+        height: 60,
+        cell_size: 10,
+        resize_world: false,
+        undo_memory: 64,
+        rewind: 10.0,
+        threads: None,
+    };
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--materials" => {
+                let path = args.next().ok_or("--materials needs a path")?;
+                options.materials = Some(PathBuf::from(path));
+            }
+            "--seed" => {
+                let seed = args.next().ok_or("--seed needs a number")?;
+                options.seed = Some(seed.parse().map_err(|_| format!("invalid seed `{seed}`"))?);
+            }
+            "--image" => {
+                let path = args.next().ok_or("--image needs a path")?;
+                options.image = Some(PathBuf::from(path));
+            }
+            "--exact-colors" => options.color_match = ColorMatch::Exact,
+            "--record" => {
+                let path = args.next().ok_or("--record needs a path")?;
+                options.record = Some(PathBuf::from(path));
+            }
+            "--tick-rate" | "--fps" => {
+                let rate = args.next().ok_or(format!("{arg} needs a number"))?;
+                let rate = rate.parse::<f64>()
+                    .ok()
+                    .filter(|&rate| rate >= 1.0)
+                    .ok_or(format!("{arg}: expected a rate of at least 1, found `{rate}`"))?;
+                if arg == "--fps" {
+                    options.fps = rate;
+                } else {
+                    options.tick_rate = rate;
+                }
+            }
+            "--width" | "--height" | "--cell-size" => {
+                let value = args.next().ok_or(format!("{arg} needs a number"))?;
+                let value = value.parse::<u32>()
+                    .ok()
+                    .filter(|&value| value >= 1)
+                    .ok_or(format!("{arg}: expected a whole number of at least 1, found `{value}`"))?;
+                match arg.as_str() {
+                    "--width" => options.width = value as usize,
+                    "--height" => options.height = value as usize,
+                    _ => options.cell_size = value,
+                }
+            }
+            "--resize-world" => options.resize_world = true,
+            "--rewind" => {
+                let seconds = args.next().ok_or("--rewind needs a number")?;
+                options.rewind = seconds.parse::<f64>()
+                    .ok()
+                    .filter(|&seconds| seconds >= 0.0)
+                    .ok_or(format!("--rewind: expected a number of seconds, found `{seconds}`"))?;
+            }
+            "--threads" => {
+                let threads = args.next().ok_or("--threads needs a number")?;
+                options.threads = Some(threads.parse::<usize>()
+                    .ok()
+                    .filter(|&threads| threads >= 1)
+                    .ok_or(format!("--threads: expected at least 1 thread, found `{threads}`"))?);
+            }
+            "--undo-memory" => {
+                let size = args.next().ok_or("--undo-memory needs a number")?;
+                options.undo_memory = size.parse().map_err(|_| format!("--undo-memory: expected a size in MiB, found `{size}`"))?;
+            }
+            _ => return Err(format!("unknown argument `{arg}`")),
+        }
+    }
+
+    if options.width * options.height > MAX_CELLS {
+        return Err(format!("{}x{} is over the limit of {MAX_CELLS} cells", options.width, options.height));
+    }
+
+    Ok(options)
+}
+
+
+/// Opens the window and runs the interactive simulation
+pub fn run(args: &[String]) {
+    let options = parse_args(args).unwrap_or_else(|err| {
+        eprintln!("error: {err}");
+        eprintln!("usage: SandSim [--materials <path>] [--seed <number>] [--image <png> [--exact-colors]] [--record <path>]");
+        eprintln!("              [--tick-rate <ticks/s>] [--fps <frames/s>]");
+        eprintln!("              [--width <cells>] [--height <cells>] [--cell-size <pixels>] [--resize-world]");
+        eprintln!("              [--undo-memory <MiB>] [--rewind <seconds>] [--threads <n>]");
+        eprintln!("       SandSim run --help");
+        eprintln!("       SandSim replay <path> [--materials <path>] [--output <path>]");
+        process::exit(2);
+    });
+
+    crate::threads_or_exit(options.threads);
+    let materials = crate::materials_or_exit(options.materials);
+
+    let sdl_context = sdl3::init().unwrap();
+    let video_subsystem = sdl_context.video().unwrap();
+
+    let window = video_subsystem.window(
+        "SandSim",
+        (options.width as u32).saturating_mul(options.cell_size).min(MAX_WINDOW_W),
+        (options.height as u32).saturating_mul(options.cell_size).min(MAX_WINDOW_H),
+    )
+        .position_centered()
+        .resizable()
+        .build()
+        .unwrap();
+
+    let mut canvas = window.into_canvas();
+
+    let mut event_pump = sdl_context.event_pump().unwrap();
+    let keyboard = sdl_context.keyboard();
+
+    // Index into `palette` of the material painted with the left button
+    let mut selected_material: usize = 0;
+
+    // Printed so a run can be reproduced with --seed
+    let seed = options.seed.unwrap_or_else(|| rand::thread_rng().r#gen());
+    println!("seed: {seed}");
+
+    let mut world = World::with_materials(options.width, options.height, seed, materials);
+    if let Some(path) = &options.image
+        && let Err(err) = load_png(path, &mut world, options.color_match) {
+        eprintln!("error: {err}");
+        process::exit(1);
+    }
+    // Every edit is recorded when running with --record
+    let mut replay = options.record.as_ref()
+        .map(|_| Replay::start(&world, options.image.clone().map(|path| (path, options.color_match))));
+    let mut pixels = vec![0u8; world.width() * world.height() * 4];
+    let texture_creator = canvas.texture_creator();
+    let mut grid_texture = GridTexture::new(&texture_creator, world.width(), world.height()).unwrap();
+    let mut camera = Camera::new(world.width(), world.height(), options.cell_size as f32);
+    let mut tool = Tool::Brush;
+    let mut brush = Brush::Circle { radius: 0 };
+    // Kept while the brush is not a spray, so switching back restores it
+    let mut spray_density: u8 = 30;
+    // Strokes and checkpoints that Ctrl+Z and Ctrl+Y step through
+    let mut history = History::new(options.undo_memory << 20);
+    // The last few seconds of ticks, scrubbed through by holding Backspace
+    let mut timeline = Timeline::new((options.rewind * options.tick_rate) as usize, KEYFRAME_INTERVAL);
+    timeline.record(&world);
+    // Whether the world is showing an earlier tick than the newest one on the timeline
+    let mut rewound = false;
+    // Cell the brush was last stamped at while a button is held
+    let mut stroke: Option<(usize, usize)> = None;
+    // Button and cell where the current line or rectangle started
+    let mut drag: Option<(MouseButton, (usize, usize))> = None;
+    let palette = world.materials().palette();
+    // Tint cells by temperature instead of drawing their plain colors
+    let mut heat_view = false;
+    // Outline the chunks that are awake, toggled with F3
+    let mut show_chunks = false;
+    // Animated GIF being recorded, toggled with G
+    let mut recording: Option<GifRecorder> = None;
+
+    // The simulation ticks at its own rate, independent of how often frames are drawn
+    let mut timestep = FixedTimestep::new(options.tick_rate);
+    let frame_duration = Duration::from_secs_f64(1.0 / options.fps);
+    let mut last_frame = Instant::now();
+    let mut tick_counter = RateCounter::new();
+    let mut frame_counter = RateCounter::new();
+    let mut frames: u64 = 0;
+    // Actual tick and frame rates are shown in the window title
+    let mut title = String::new();
+    // Grid size the window last changed to, and when, with `--resize-world`
+    let mut pending_resize: Option<((usize, usize), Instant)> = None;
+
+    'running: loop {
+        let frame_start = Instant::now();
+
+        // === LAYOUT ===
+        // Mouse positions are in window coordinates, drawing is in output pixels,
+        // which differ on high-DPI screens
+        let (window_w, window_h) = canvas.window().size();
+        let (screen_w, screen_h) = canvas.output_size().unwrap_or((window_w, window_h));
+
+        if options.resize_world {
+            let width = (window_w / options.cell_size).max(1) as usize;
+            let height = (window_h / options.cell_size).max(1) as usize;
+            if (width, height) == (world.width(), world.height()) {
+                pending_resize = None;
+            } else if pending_resize.is_none_or(|(size, _)| size != (width, height)) {
+                pending_resize = Some(((width, height), Instant::now()));
+            } else if pending_resize.is_some_and(|(_, since)| since.elapsed() >= RESIZE_DELAY) {
+                pending_resize = None;
+                // A replay cannot follow the world changing size
+                stop_replay(&mut replay, &world, options.record.as_deref());
+                world.resize(width, height);
+                history.clear();
+                timeline.clear();
+                rewound = false;
+            }
+        }
+
+        if (grid_texture.width(), grid_texture.height()) != (world.width(), world.height()) {
+            stop_recording(&mut recording);
+            pixels = vec![0u8; world.width() * world.height() * 4];
+            grid_texture = GridTexture::new(&texture_creator, world.width(), world.height()).unwrap();
+            // Draws the whole new texture this frame
+            frames = 0;
+        }
+
+        let (screen_w, screen_h) = (screen_w as f32, screen_h as f32);
+        camera.clamp(screen_w, screen_h, world.width(), world.height());
+        let viewport = camera.viewport(screen_w, screen_h, world.width(), world.height());
+        let visible = viewport.visible(screen_w, screen_h);
+        // The minimap only shows while part of the world is off screen
+        let minimap = visible
+            .filter(|(cells, _)| cells.size() != (world.width() as u32, world.height() as u32))
+            .map(|_| minimap(screen_w, screen_h, &world));
+
+        // === INPUT ===
+        // Get mouse state from the event pump
+        let mouse_state = event_pump.mouse_state();
+        let dpi_scale = screen_w / window_w.max(1) as f32;
+        let (mouse_x, mouse_y) = (mouse_state.x() * dpi_scale, mouse_state.y() * dpi_scale);
+        // The grid cell under window position (`x`, `y`), unless the minimap covers it
+        let grid_cell = |x: f32, y: f32| {
+            let (x, y) = (x * dpi_scale, y * dpi_scale);
+            match minimap {
+                Some(minimap) if minimap.cell_at(x, y).is_some() => None,
+                _ => viewport.cell_at(x, y),
+            }
+        };
+        let hovered = grid_cell(mouse_state.x(), mouse_state.y());
+
+        if let Some(minimap) = minimap
+            && let Some((x, y)) = minimap.cell_at(mouse_x, mouse_y) {
+            // Clicking the minimap looks at that spot instead of painting under it
+            if mouse_state.left() && drag.is_none() {
+                camera.center_x = x as f32 + 0.5;
+                camera.center_y = y as f32 + 0.5;
+            }
+        }
+
+        // Whether the brush was already stamped this frame by following the mouse
+        let mut stamped = false;
+
+        for event in event_pump.poll_iter() {
+            match event {
+                Event::Quit {..}
+                | Event::KeyDown { keycode: Some(Keycode::Escape), .. } => {
+                    break 'running;
+                },
+                Event::MouseButtonDown { mouse_btn: button @ (MouseButton::Left | MouseButton::Right), x, y, .. } => {
+                    let material = if button == MouseButton::Left { palette[selected_material] } else { AIR };
+                    match (tool, grid_cell(x, y)) {
+                        (Tool::Brush, Some((x, y))) => {
+                            let edit = Edit { tick: world.tick(), x, y, shape: Shape::Brush(brush), material };
+                            paint(&mut world, &mut replay, &mut history, edit);
+                            stroke = Some((x, y));
+                            stamped = true;
+                        }
+                        (Tool::Line | Tool::Rect, Some(cell)) if drag.is_none() => drag = Some((button, cell)),
+                        (Tool::Fill, Some((x, y))) => {
+                            let edit = Edit { tick: world.tick(), x, y, shape: Shape::Fill, material };
+                            paint(&mut world, &mut replay, &mut history, edit);
+                        }
+                        _ => {}
+                    }
+                }
+                Event::MouseButtonUp { mouse_btn: button @ (MouseButton::Left | MouseButton::Right), x, y, .. } => {
+                    stroke = None;
+                    if let Some((drag_button, (start_x, start_y))) = drag
+                        && drag_button == button {
+                        drag = None;
+                        let (end_x, end_y) = viewport.nearest_cell(x * dpi_scale, y * dpi_scale);
+                        let shape = match tool {
+                            Tool::Line => Shape::Line { x: end_x, y: end_y, brush },
+                            _ => Shape::Rect { x: end_x, y: end_y },
+                        };
+                        let material = if button == MouseButton::Left { palette[selected_material] } else { AIR };
+                        let edit = Edit { tick: world.tick(), x: start_x, y: start_y, shape, material };
+                        paint(&mut world, &mut replay, &mut history, edit);
+                    }
+                    // Everything painted since the button went down is undone in one go
+                    history.end_stroke();
+                }
+                Event::MouseWheel { y, mouse_x, mouse_y, .. } => {
+                    let modifiers = keyboard.mod_state();
+                    if modifiers.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD) {
+                        let count = palette.len() as i32;
+                        selected_material = (selected_material as i32 + y as i32).rem_euclid(count) as usize;
+                    } else if modifiers.intersects(Mod::LCTRLMOD | Mod::RCTRLMOD) {
+                        let radius = brush.radius() + y.signum() as i32;
+                        brush = brush.with_radius(radius.clamp(0, shape::MAX_BRUSH_RADIUS));
+                    } else {
+                        let factor = ZOOM_STEP.powf(y);
+                        camera.zoom_at(screen_w, screen_h, mouse_x * dpi_scale, mouse_y * dpi_scale, factor);
+                    }
+                }
+                // Dragging with the middle button pans the camera
+                Event::MouseMotion { mousestate, xrel, yrel, .. } if mousestate.middle() => {
+                    camera.pan(xrel * dpi_scale, yrel * dpi_scale);
+                }
+                // Every motion event is painted as a line from the previous one, so
+                // fast drags leave no gaps however low the frame rate is
+                Event::MouseMotion { mousestate, x, y, .. }
+                    if tool == Tool::Brush && (mousestate.left() || mousestate.right()) => {
+                    let material = if mousestate.left() { palette[selected_material] } else { AIR };
+                    let cell = grid_cell(x, y);
+                    if let Some((x, y)) = cell {
+                        let (shape, x, y) = match stroke {
+                            Some((from_x, from_y)) => (Shape::Line { x, y, brush }, from_x, from_y),
+                            None => (Shape::Brush(brush), x, y),
+                        };
+                        let edit = Edit { tick: world.tick(), x, y, shape, material };
+                        paint(&mut world, &mut replay, &mut history, edit);
+                        stamped = true;
+                    }
+                    // Leaving the grid ends the stroke instead of joining it up across the gap
+                    stroke = cell;
+                }
+                Event::KeyDown { keycode: Some(key), .. } => {
+                    let slot = match key {
+                        Keycode::_1 => Some(0),
+                        Keycode::_2 => Some(1),
+                        Keycode::_3 => Some(2),
+                        Keycode::_4 => Some(3),
+                        Keycode::_5 => Some(4),
+                        Keycode::_6 => Some(5),
+                        Keycode::_7 => Some(6),
+                        Keycode::_8 => Some(7),
+                        Keycode::_9 => Some(8),
+                        Keycode::_0 => Some(9),
+                        Keycode::Space => {
+                            timestep.toggle_pause();
+                            None
+                        }
+                        Keycode::Period => {
+                            timestep.step_once();
+                            None
+                        }
+                        Keycode::Equals | Keycode::Plus | Keycode::KpPlus => {
+                            timestep.faster();
+                            None
+                        }
+                        Keycode::Minus | Keycode::KpMinus => {
+                            timestep.slower();
+                            None
+                        }
+                        Keycode::H => {
+                            heat_view = !heat_view;
+                            None
+                        }
+                        Keycode::F3 => {
+                            show_chunks = !show_chunks;
+                            None
+                        }
+                        Keycode::B => {
+                            // Pressed again, B switches between circle, square and spray
+                            if tool == Tool::Brush {
+                                let radius = brush.radius();
+                                brush = match brush {
+                                    Brush::Circle { .. } => Brush::Square { radius },
+                                    Brush::Square { .. } => Brush::Spray { radius, density: spray_density },
+                                    Brush::Spray { .. } => Brush::Circle { radius },
+                                };
+                            }
+                            tool = Tool::Brush;
+                            drag = None;
+                            None
+                        }
+                        Keycode::L | Keycode::R | Keycode::F => {
+                            tool = match key {
+                                Keycode::L => Tool::Line,
+                                Keycode::R => Tool::Rect,
+                                _ => Tool::Fill,
+                            };
+                            drag = None;
+                            None
+                        }
+                        Keycode::LeftBracket | Keycode::RightBracket => {
+                            let change = if key == Keycode::LeftBracket { -1 } else { 1 };
+                            // With Shift the brackets change how thick a spray is instead of its size
+                            if keyboard.mod_state().intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD) {
+                                spray_density = (spray_density as i32 + change * 10).clamp(10, 100) as u8;
+                                if let Brush::Spray { radius, .. } = brush {
+                                    brush = Brush::Spray { radius, density: spray_density };
+                                }
+                            } else {
+                                brush = brush.with_radius((brush.radius() + change).clamp(0, shape::MAX_BRUSH_RADIUS));
+                            }
+                            None
+                        }
+                        Keycode::Z | Keycode::Y if keyboard.mod_state().intersects(Mod::LCTRLMOD | Mod::RCTRLMOD) => {
+                            // Ctrl+Shift+Z redoes too
+                            let redo = key == Keycode::Y || keyboard.mod_state().intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD);
+                            history.end_stroke();
+                            let available = if redo { history.redo_len() } else { history.undo_len() };
+                            if available == 0 {
+                                println!("nothing to {}", if redo { "redo" } else { "undo" });
+                            } else {
+                                // A replay cannot follow the world being changed back
+                                stop_replay(&mut replay, &world, options.record.as_deref());
+                                if redo {
+                                    history.redo(&mut world);
+                                } else {
+                                    history.undo(&mut world);
+                                }
+                            }
+                            stroke = None;
+                            drag = None;
+                            None
+                        }
+                        Keycode::K => {
+                            history.checkpoint(&world);
+                            println!("checkpoint at tick {}", world.tick());
+                            None
+                        }
+                        Keycode::Home => {
+                            // Zooms all the way out, which clamping then turns into the whole world
+                            camera = Camera::new(world.width(), world.height(), 0.0);
+                            None
+                        }
+                        Keycode::P => {
+                            // Off-screen cells may not have been drawn for a few frames
+                            let all = Rect::new(0, 0, world.width() as u32, world.height() as u32);
+                            render_cells(&world, &mut pixels, heat_view, all);
+                            let path = timestamped("png");
+                            match save_png(&path, &pixels, world.width(), world.height(), export_scale(&world, options.cell_size)) {
+                                Ok(()) => println!("saved screenshot to {}", path.display()),
+                                Err(err) => eprintln!("error: {err}"),
+                            }
+                            None
+                        }
+                        Keycode::G => {
+                            match recording {
+                                Some(_) => stop_recording(&mut recording),
+                                None => {
+                                    let path = timestamped("gif");
+                                    // GIF delays are in hundredths of a second
+                                    let delay = (100.0 / options.fps).round().max(1.0) as u16;
+                                    let scale = export_scale(&world, options.cell_size);
+                                    match GifRecorder::create(&path, world.width(), world.height(), scale, delay) {
+                                        Ok(recorder) => {
+                                            println!("recording to {}", path.display());
+                                            recording = Some(recorder);
+                                        }
+                                        Err(err) => eprintln!("error: {err}"),
+                                    }
+                                }
+                            }
+                            None
+                        }
+                        Keycode::F5 => {
+                            match world.save(Path::new(SAVE_PATH)) {
+                                Ok(()) => println!("saved to {SAVE_PATH}"),
+                                Err(err) => eprintln!("error: {err}"),
+                            }
+                            None
+                        }
+                        Keycode::F9 => {
+                            match World::load(Path::new(SAVE_PATH), world.materials().clone()) {
+                                Ok(loaded) => {
+                                    // A replay cannot follow the world jumping to a saved state
+                                    stop_replay(&mut replay, &world, options.record.as_deref());
+                                    world = loaded;
+                                    history.clear();
+                                    timeline.clear();
+                                    rewound = false;
+                                    println!("loaded {SAVE_PATH}");
+                                }
+                                Err(err) => eprintln!("error: {err}"),
+                            }
+                            None
+                        }
+                        _ => None,
+                    };
+                    if let Some(slot) = slot.filter(|&slot| slot < palette.len()) {
+                        selected_material = slot;
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        // Holding the brush still keeps painting, so sprays build up and powders pour
+        let mouse_state = event_pump.mouse_state();
+        if tool == Tool::Brush
+            && !stamped
+            && let Some((x, y)) = grid_cell(mouse_state.x(), mouse_state.y()) {
+            for (button, material) in [(MouseButton::Left, palette[selected_material]), (MouseButton::Right, AIR)] {
+                if mouse_state.is_mouse_button_pressed(button) {
+                    let edit = Edit { tick: world.tick(), x, y, shape: Shape::Brush(brush), material };
+                    paint(&mut world, &mut replay, &mut history, edit);
+                    stroke = Some((x, y));
+                }
+            }
+        }
+
+        // === REWIND ===
+        // Holding Backspace scrubs back through the timeline, with Shift forward again
+        if event_pump.keyboard_state().is_scancode_pressed(Scancode::Backspace)
+            && let (Some(oldest), Some(newest)) = (timeline.oldest_tick(), timeline.newest_tick()) {
+            let target = if keyboard.mod_state().intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD) {
+                (world.tick() + REWIND_STEP).min(newest)
+            } else {
+                world.tick().saturating_sub(REWIND_STEP).max(oldest)
+            };
+            if target != world.tick() {
+                if !rewound {
+                    // A replay cannot follow the world going back in time
+                    stop_replay(&mut replay, &world, options.record.as_deref());
+                    if !timestep.paused() {
+                        timestep.toggle_pause();
+                    }
+                }
+                // Back at the newest tick the simulation simply carries on
+                rewound = timeline.seek(&mut world, target) && target != newest;
+            }
+        }
+
+        // === SIMULATION ===
+        let ticks = timestep.advance(frame_start - last_frame);
+        last_frame = frame_start;
+        if ticks > 0 && rewound {
+            // Carrying on from an earlier tick replaces everything that came after it
+            timeline.branch(world.tick());
+            rewound = false;
+        }
+        for _ in 0..ticks {
+            world.step();
+            timeline.record(&world);
+        }
+        tick_counter.add(ticks);
+
+        // === RENDER ===
+        // Background color
+        canvas.set_draw_color(Color::RGBA(0, 0, 0, 255));
+        canvas.clear();
+
+        // Only cells on screen are drawn every frame, unless the whole world is
+        // needed for a GIF or the minimap is due for a refresh
+        let all = Rect::new(0, 0, world.width() as u32, world.height() as u32);
+        let cells = match visible {
+            _ if recording.is_some() || frames.is_multiple_of(MINIMAP_REFRESH) => Some(all),
+            Some((cells, _)) => Some(cells),
+            None => None,
+        };
+        if let Some(cells) = cells {
+            render_cells(&world, &mut pixels, heat_view, cells);
+            grid_texture.update(&pixels, cells);
+        }
+
+        if let Some(recorder) = &mut recording
+            && let Err(err) = recorder.add_frame(&pixels) {
+            eprintln!("error: {err}");
+            recording = None;
+        }
+
+        if let Some((cells, screen)) = visible {
+            grid_texture.copy(&mut canvas, Some(FRect::from(cells)), screen);
+        }
+
+        // Outline of what the current tool would paint
+        let (width, height) = (world.width(), world.height());
+        let footprint = match (tool, drag, hovered) {
+            (Tool::Line, Some((_, start)), _) => {
+                let end = viewport.nearest_cell(mouse_x, mouse_y);
+                shape::line(start, end)
+                    .into_iter()
+                    .flat_map(|(x, y)| brush.footprint(x, y, width, height))
+                    .collect()
+            }
+            (Tool::Rect, Some((_, start)), _) => shape::rect(start, viewport.nearest_cell(mouse_x, mouse_y)),
+            (Tool::Brush | Tool::Line, None, Some((x, y))) => brush.footprint(x, y, width, height),
+            (_, None, Some(cell)) => vec![cell],
+            _ => Vec::new(),
+        };
+        canvas.set_draw_color(Color::RGB(255, 255, 255));
+        viewport.outline(&mut canvas, &footprint);
+
+        if show_chunks {
+            canvas.set_draw_color(Color::RGB(0, 200, 0));
+            for (x, y, chunk_w, chunk_h) in world.awake_chunks() {
+                let _ = canvas.draw_rect(FRect::new(
+                    viewport.x + x as f32 * viewport.scale,
+                    viewport.y + y as f32 * viewport.scale,
+                    chunk_w as f32 * viewport.scale,
+                    chunk_h as f32 * viewport.scale,
+                ));
+            }
+        }
+
+        if let Some(minimap) = minimap {
+            let map = minimap.rect();
+            canvas.set_draw_color(Color::RGB(40, 40, 40));
+            let _ = canvas.fill_rect(FRect::new(map.x - 2.0, map.y - 2.0, map.w + 4.0, map.h + 4.0));
+            grid_texture.copy(&mut canvas, None, map);
+
+            // Outline of the part of the world on screen
+            if let Some((cells, _)) = visible {
+                canvas.set_draw_color(Color::RGB(255, 255, 255));
+                let _ = canvas.draw_rect(FRect::new(
+                    map.x + cells.x() as f32 * minimap.scale,
+                    map.y + cells.y() as f32 * minimap.scale,
+                    cells.width() as f32 * minimap.scale,
+                    cells.height() as f32 * minimap.scale,
+                ));
+            }
+        }
+
+        let (red, green, blue) = world.materials().get(palette[selected_material]).color;
+        canvas.set_draw_color(Color::RGB(red, green, blue));
+        let square = Rect::new(10, 10, 40, 40);
+        let _ = canvas.fill_rect(square);
+
+        canvas.present();
+        frame_counter.add(1);
+        frames += 1;
+
+        let state = match timeline.newest_tick() {
+            Some(newest) if rewound => format!("rewound {} ticks", newest - world.tick()),
+            _ if timestep.paused() => "paused".to_string(),
+            _ => format!("{}x", timestep.speed()),
+        };
+        let state = if show_chunks {
+            let (chunks_x, chunks_y) = world.chunks();
+            format!("{state}, {}/{} chunks awake", world.awake_chunks().count(), chunks_x * chunks_y)
+        } else {
+            state
+        };
+        let status = format!(
+            "SandSim - {} {brush} - {:.0} ticks/s, {:.0} fps, {state}",
+            tool.name(),
+            tick_counter.rate(),
+            frame_counter.rate(),
+        );
+        if status != title {
+            let _ = canvas.window_mut().set_title(&status);
+            title = status;
+        }
+
+        // Sleep off whatever is left of this frame
+        if let Some(remaining) = frame_duration.checked_sub(frame_start.elapsed()) {
+            sleep(remaining);
+        }
+    }
+
+    stop_replay(&mut replay, &world, options.record.as_deref());
+
+    // Quitting mid-recording still leaves a playable GIF
+    stop_recording(&mut recording);
+}
@@ -0,0 +1,249 @@
+use sandsim::world::MAX_CELLS;
+use sandsim::{load_png, save_png, ColorMatch, Replay, World};
+use std::path::PathBuf;
+use std::process;
+use std::time::Instant;
+
+
+const USAGE: &str = "\
+usage: SandSim run --ticks <n> [options]
+
+  --input <path>             start from a world saved with F5 or --output
+  --image <png>              start from an image, one material per pixel
+  --exact-colors             fail on image colors that match no material
+  --width <cells>            grid width without --input, 80 by default
+  --height <cells>           grid height without --input, 60 by default
+  --materials <path>         materials file
+  --seed <number>            simulation seed, overriding the one in --input
+  --ticks <n>                number of ticks to simulate
+  --output <path>            save the final world here
+  --snapshot-every <n> <pattern>
+                             write a PNG every n ticks, `%d` or `%05d` in the
+                             pattern is replaced by the tick number
+  --scale <n>                draw each cell as n x n pixels in snapshots
+  --threads <n>              threads updating the world, one per core by default";
+
+
+/// Command line options of `SandSim run`
+struct Options {
+    input: Option<PathBuf>,
+    image: Option<PathBuf>,
+    color_match: ColorMatch,
+    width: usize,
+    height: usize,
+    materials: Option<PathBuf>,
+    seed: Option<u64>,
+    ticks: Option<u64>,
+    output: Option<PathBuf>,
+    /// Interval and file name pattern of PNG snapshots
+    snapshots: Option<(u64, String)>,
+    scale: usize,
+    threads: Option<usize>,
+}
+
+fn parse_args(args: &[String]) -> Result<Options, String> {
+    let mut options = Options {
+        input: None,
+        image: None,
+        color_match: ColorMatch::Nearest,
+        width: 80,
+        height: 60,
+        materials: None,
+        seed: None,
+        ticks: None,
+        output: None,
+        snapshots: None,
+        scale: 1,
+        threads: None,
+    };
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().cloned().ok_or(format!("{arg} needs a value"));
+        let number = |text: String| text.parse::<u64>().map_err(|_| format!("{arg}: invalid number `{text}`"));
+
+        match arg.as_str() {
+            "--input" => options.input = Some(PathBuf::from(value()?)),
+            "--image" => options.image = Some(PathBuf::from(value()?)),
+            "--exact-colors" => options.color_match = ColorMatch::Exact,
+            "--width" => options.width = number(value()?)? as usize,
+            "--height" => options.height = number(value()?)? as usize,
+            "--materials" => options.materials = Some(PathBuf::from(value()?)),
+            "--seed" => options.seed = Some(number(value()?)?),
+            "--ticks" => options.ticks = Some(number(value()?)?),
+            "--output" => options.output = Some(PathBuf::from(value()?)),
+            "--snapshot-every" => {
+                let every = number(value()?)?;
+                if every == 0 {
+                    return Err("--snapshot-every needs an interval of at least 1".to_string());
+                }
+                options.snapshots = Some((every, value()?));
+            }
+            "--scale" | "--threads" => {
+                let value = number(value()?)?;
+                if value == 0 {
+                    return Err(format!("{arg} needs a number of at least 1"));
+                }
+                match arg.as_str() {
+                    "--scale" => options.scale = value as usize,
+                    _ => options.threads = Some(value as usize),
+                }
+            }
+            "--help" | "-h" => {
+                println!("{USAGE}");
+                process::exit(0);
+            }
+            _ => return Err(format!("unknown argument `{arg}`")),
+        }
+    }
+
+    if options.ticks.is_none() {
+        return Err("--ticks is required".to_string());
+    }
+    if options.input.is_some() && options.image.is_some() {
+        return Err("--input and --image cannot be combined".to_string());
+    }
+    if options.width == 0 || options.height == 0 {
+        return Err("the grid needs at least one cell".to_string());
+    }
+    if options.width.checked_mul(options.height).is_none_or(|cells| cells > MAX_CELLS) {
+        return Err(format!("{}x{} is over the limit of {MAX_CELLS} cells", options.width, options.height));
+    }
+
+    Ok(options)
+}
+
+
+/// Replaces a printf-style `%d` or `%05d` in `pattern` with `tick`
+fn snapshot_path(pattern: &str, tick: u64) -> PathBuf {
+    let Some(start) = pattern.find('%') else {
+        return PathBuf::from(format!("{pattern}{tick}"));
+    };
+    let Some(end) = pattern[start..].find('d').map(|offset| start + offset) else {
+        return PathBuf::from(pattern);
+    };
+
+    let width = pattern[start + 1..end].parse::<usize>().unwrap_or(0);
+    PathBuf::from(format!("{}{tick:0width$}{}", &pattern[..start], &pattern[end + 1..]))
+}
+
+
+/// Runs the simulation without a window: `SandSim run ...`
+pub fn run(args: &[String]) {
+    let options = parse_args(args).unwrap_or_else(|err| {
+        eprintln!("error: {err}");
+        eprintln!("{USAGE}");
+        process::exit(2);
+    });
+    let fail = |err: &dyn std::fmt::Display| -> ! {
+        eprintln!("error: {err}");
+        process::exit(1);
+    };
+
+    crate::threads_or_exit(options.threads);
+    let materials = crate::materials_or_exit(options.materials);
+    let mut world = match &options.input {
+        Some(path) => World::load(path, materials).unwrap_or_else(|err| fail(&err)),
+        None => World::with_materials(options.width, options.height, options.seed.unwrap_or(0), materials),
+    };
+    if let Some(seed) = options.seed {
+        world.set_seed(seed);
+    }
+    if let Some(path) = &options.image
+        && let Err(err) = load_png(path, &mut world, options.color_match) {
+        fail(&err);
+    }
+
+    let ticks = options.ticks.unwrap_or(0);
+    let mut pixels = vec![0u8; world.width() * world.height() * 4];
+    let mut snapshot = |world: &World, tick: u64| {
+        if let Some((every, pattern)) = &options.snapshots
+            && tick.is_multiple_of(*every) {
+            world.render(&mut pixels);
+            let path = snapshot_path(pattern, tick);
+            if let Err(err) = save_png(&path, &pixels, world.width(), world.height(), options.scale) {
+                fail(&err);
+            }
+        }
+    };
+
+    let start = Instant::now();
+    snapshot(&world, 0);
+    for tick in 1..=ticks {
+        world.step();
+        snapshot(&world, tick);
+    }
+    let elapsed = start.elapsed().as_secs_f64();
+
+    if let Some(path) = &options.output
+        && let Err(err) = world.save(path) {
+        fail(&err);
+    }
+
+    println!(
+        "simulated {ticks} ticks of a {}x{} world (seed {}) in {elapsed:.2}s, {:.0} ticks/s",
+        world.width(),
+        world.height(),
+        world.seed(),
+        ticks as f64 / elapsed.max(1e-9),
+    );
+}
+
+
+/// Plays a recorded session back without a window and checks that it ends
+/// with the same grid: `SandSim replay <path> ...`
+pub fn replay(args: &[String]) {
+    let usage = "usage: SandSim replay <path> [--materials <path>] [--output <path>]";
+    let mut path = None;
+    let mut materials = None;
+    let mut output = None;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().map(PathBuf::from).unwrap_or_else(|| {
+            eprintln!("error: {arg} needs a path");
+            eprintln!("{usage}");
+            process::exit(2);
+        });
+
+        match arg.as_str() {
+            "--materials" => materials = Some(value()),
+            "--output" => output = Some(value()),
+            _ if path.is_none() && !arg.starts_with("--") => path = Some(PathBuf::from(arg)),
+            _ => {
+                eprintln!("error: unknown argument `{arg}`");
+                eprintln!("{usage}");
+                process::exit(2);
+            }
+        }
+    }
+    let Some(path) = path else {
+        eprintln!("error: no replay file given");
+        eprintln!("{usage}");
+        process::exit(2);
+    };
+    let fail = |err: &dyn std::fmt::Display| -> ! {
+        eprintln!("error: {err}");
+        process::exit(1);
+    };
+
+    let replay = Replay::load(&path).unwrap_or_else(|err| fail(&err));
+    let world = replay.play(crate::materials_or_exit(materials)).unwrap_or_else(|err| fail(&err));
+
+    if let Some(path) = &output
+        && let Err(err) = world.save(path) {
+        fail(&err);
+    }
+
+    match replay.end {
+        Some((tick, expected)) if world.checksum() != expected => {
+            eprintln!(
+                "checksum mismatch after {tick} ticks: recorded {expected:016x}, replayed {:016x}",
+                world.checksum(),
+            );
+            process::exit(1);
+        }
+        Some((tick, expected)) => println!("replayed {} edits over {tick} ticks, checksum {expected:016x} matches", replay.edits.len()),
+        None => println!("replayed {} edits over {} ticks, no checksum recorded", replay.edits.len(), world.tick()),
+    }
+}
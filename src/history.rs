@@ -0,0 +1,283 @@
+use std::collections::{HashMap, VecDeque};
+use std::mem;
+
+use crate::cell::Cell;
+use crate::replay::Edit;
+use crate::world::World;
+
+
+/// Something that can be undone and redone
+enum Change {
+    /// Cells painted over by one stroke: index, cell before and cell after
+    Stroke(Vec<(usize, Cell, Cell)>),
+    /// The whole world as it was at a checkpoint, or, once undone, as it was
+    /// before going back to it
+    Checkpoint(Box<World>),
+}
+
+impl Change {
+    /// Rough number of bytes the change keeps alive
+    fn size(&self) -> usize {
+        match self {
+            Change::Stroke(cells) => cells.len() * mem::size_of::<(usize, Cell, Cell)>(),
+            Change::Checkpoint(world) => world.cells().len() * (mem::size_of::<Cell>() + 1),
+        }
+    }
+}
+
+
+/// Undo and redo stacks for edits made to a world, and for checkpoints that
+/// return the whole simulation to an earlier state
+///
+/// Once the changes kept take more than the memory cap, the oldest ones are
+/// forgotten
+pub struct History {
+    undo: VecDeque<Change>,
+    redo: Vec<Change>,
+    /// Cells before and after the stroke being painted, by index
+    stroke: HashMap<usize, (Cell, Cell)>,
+    /// Bytes used by `undo` and `redo`
+    size: usize,
+    max_size: usize,
+}
+
+impl History {
+    /// An empty history that keeps at most about `max_size` bytes of changes
+    pub fn new(max_size: usize) -> History {
+        History {
+            undo: VecDeque::new(),
+            redo: Vec::new(),
+            stroke: HashMap::new(),
+            size: 0,
+            max_size,
+        }
+    }
+
+    /// Applies `edit` to `world` as part of the current stroke, which starts with
+    /// the first edit after [`History::end_stroke`]
+    pub fn paint(&mut self, world: &mut World, edit: &Edit) {
+        let width = world.width();
+        let cells = edit.cells(world);
+        for &(x, y) in &cells {
+            let idx = x + y * width;
+            let before = world.cells()[idx];
+            self.stroke.entry(idx).or_insert((before, before));
+        }
+
+        edit.apply(world);
+        for &(x, y) in &cells {
+            let idx = x + y * width;
+            if let Some((_, after)) = self.stroke.get_mut(&idx) {
+                *after = world.cells()[idx];
+            }
+        }
+    }
+
+    /// Finishes the current stroke, so it is undone in one go
+    pub fn end_stroke(&mut self) {
+        if self.stroke.is_empty() {
+            return;
+        }
+
+        let mut cells: Vec<_> = self.stroke
+            .drain()
+            .map(|(idx, (before, after))| (idx, before, after))
+            .collect();
+        cells.sort_unstable_by_key(|&(idx, _, _)| idx);
+        self.push(Change::Stroke(cells));
+    }
+
+    /// Remembers the whole of `world`, so undoing back to this point rewinds the simulation too
+    pub fn checkpoint(&mut self, world: &World) {
+        self.end_stroke();
+        self.push(Change::Checkpoint(Box::new(world.clone())));
+    }
+
+    /// Takes back the latest stroke or checkpoint; returns false when there is nothing left to undo
+    pub fn undo(&mut self, world: &mut World) -> bool {
+        self.end_stroke();
+        let Some(change) = self.undo.pop_back() else {
+            return false;
+        };
+
+        let change = match change {
+            Change::Stroke(cells) => {
+                restore(world, cells.iter().map(|&(idx, before, _)| (idx, before)));
+                Change::Stroke(cells)
+            }
+            Change::Checkpoint(mut saved) => {
+                mem::swap(world, &mut saved);
+                Change::Checkpoint(saved)
+            }
+        };
+        self.redo.push(change);
+        true
+    }
+
+    /// Makes the latest undone change again; returns false when there is nothing to redo
+    pub fn redo(&mut self, world: &mut World) -> bool {
+        self.end_stroke();
+        let Some(change) = self.redo.pop() else {
+            return false;
+        };
+
+        let change = match change {
+            Change::Stroke(cells) => {
+                restore(world, cells.iter().map(|&(idx, _, after)| (idx, after)));
+                Change::Stroke(cells)
+            }
+            Change::Checkpoint(mut saved) => {
+                mem::swap(world, &mut saved);
+                Change::Checkpoint(saved)
+            }
+        };
+        self.undo.push_back(change);
+        true
+    }
+
+    /// Forgets everything, for when the world is replaced by one the changes do not belong to
+    pub fn clear(&mut self) {
+        self.undo.clear();
+        self.redo.clear();
+        self.stroke.clear();
+        self.size = 0;
+    }
+
+    /// Number of changes that can be undone
+    pub fn undo_len(&self) -> usize {
+        self.undo.len()
+    }
+
+    /// Number of changes that can be redone
+    pub fn redo_len(&self) -> usize {
+        self.redo.len()
+    }
+
+    /// Adds a new change, which makes everything undone so far impossible to redo
+    fn push(&mut self, change: Change) {
+        let dropped: usize = self.redo.drain(..).map(|change| change.size()).sum();
+        self.size = self.size.saturating_sub(dropped);
+        self.size += change.size();
+        self.undo.push_back(change);
+
+        // The newest change is kept even when it alone is over the cap
+        while self.size > self.max_size && self.undo.len() > 1 {
+            if let Some(oldest) = self.undo.pop_front() {
+                self.size = self.size.saturating_sub(oldest.size());
+            }
+        }
+    }
+}
+
+/// Writes `cells` back into `world`, skipping any that no longer fit after a resize
+fn restore(world: &mut World, cells: impl Iterator<Item = (usize, Cell)>) {
+    let world_cells = world.cells_mut();
+    for (idx, cell) in cells {
+        if let Some(slot) = world_cells.get_mut(idx) {
+            *slot = cell;
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::{MaterialId, AIR};
+    use crate::shape::{Brush, Shape};
+
+    fn dot(x: usize, y: usize, material: MaterialId) -> Edit {
+        Edit { tick: 0, x, y, shape: Shape::Brush(Brush::Circle { radius: 0 }), material }
+    }
+
+    fn stone(world: &World) -> MaterialId {
+        world.materials().id("stone").expect("built-in material")
+    }
+
+    #[test]
+    fn strokes_undo_and_redo_in_one_go() {
+        let mut world = World::new(8, 8, 0);
+        let stone = stone(&world);
+        let mut history = History::new(usize::MAX);
+
+        history.paint(&mut world, &dot(1, 1, stone));
+        history.paint(&mut world, &dot(2, 1, stone));
+        history.end_stroke();
+        history.paint(&mut world, &dot(5, 5, stone));
+        assert_eq!(history.undo_len(), 1, "the open stroke is not pushed yet");
+
+        assert!(history.undo(&mut world));
+        assert_eq!(world.get(5, 5), AIR);
+        assert_eq!(world.get(1, 1), stone);
+        assert!(history.undo(&mut world));
+        assert_eq!((world.get(1, 1), world.get(2, 1)), (AIR, AIR));
+        assert!(!history.undo(&mut world));
+
+        assert!(history.redo(&mut world));
+        assert_eq!((world.get(1, 1), world.get(2, 1), world.get(5, 5)), (stone, stone, AIR));
+        assert_eq!((history.undo_len(), history.redo_len()), (1, 1));
+
+        history.paint(&mut world, &dot(0, 0, stone));
+        history.end_stroke();
+        assert_eq!(history.redo_len(), 0, "a new stroke drops what was undone");
+        assert!(!history.redo(&mut world));
+    }
+
+    #[test]
+    fn repainted_cells_keep_their_first_before_and_last_after() {
+        let mut world = World::new(8, 8, 0);
+        let [stone, steel] = ["stone", "steel"].map(|name| world.materials().id(name).unwrap());
+        let mut history = History::new(usize::MAX);
+
+        history.paint(&mut world, &dot(3, 3, stone));
+        history.paint(&mut world, &dot(3, 3, steel));
+        history.end_stroke();
+        assert_eq!(history.undo_len(), 1);
+
+        history.undo(&mut world);
+        assert_eq!(world.get(3, 3), AIR);
+        history.redo(&mut world);
+        assert_eq!(world.get(3, 3), steel);
+    }
+
+    #[test]
+    fn checkpoints_swap_the_whole_world() {
+        let mut world = World::new(16, 16, 3);
+        let sand = world.materials().id("sand").unwrap();
+        world.set(8, 0, sand);
+        let mut history = History::new(usize::MAX);
+
+        history.checkpoint(&world);
+        let saved = (world.tick(), world.checksum());
+        for _ in 0..10 {
+            world.step();
+        }
+        let later = (world.tick(), world.checksum());
+
+        assert!(history.undo(&mut world));
+        assert_eq!((world.tick(), world.checksum()), saved);
+        assert!(history.redo(&mut world));
+        assert_eq!((world.tick(), world.checksum()), later);
+    }
+
+    #[test]
+    fn oldest_changes_are_forgotten_past_the_memory_cap() {
+        let mut world = World::new(8, 8, 0);
+        let stone = stone(&world);
+        let one_cell = mem::size_of::<(usize, Cell, Cell)>();
+
+        let mut history = History::new(one_cell * 5 / 2);
+        for x in 0..4 {
+            history.paint(&mut world, &dot(x, 0, stone));
+            history.end_stroke();
+        }
+        assert_eq!(history.undo_len(), 2);
+        while history.undo(&mut world) {}
+        assert_eq!((world.get(0, 0), world.get(1, 0), world.get(2, 0), world.get(3, 0)), (stone, stone, AIR, AIR));
+
+        let mut history = History::new(0);
+        history.paint(&mut world, &dot(0, 0, stone));
+        history.end_stroke();
+        assert_eq!(history.undo_len(), 1, "the newest change is kept even over the cap");
+    }
+}
@@ -0,0 +1,394 @@
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use png::{ColorType, Transformations};
+
+use crate::material::{MaterialId, MaterialRegistry, AIR};
+use crate::world::World;
+
+
+/// Everything that can go wrong while reading or writing an image
+#[derive(Debug)]
+pub enum ImageError {
+    /// The file could not be opened or created
+    Io { path: PathBuf, source: io::Error },
+    /// The file is not a PNG image the decoder understands
+    Decode { path: PathBuf, source: png::DecodingError },
+    /// Writing a PNG image failed
+    PngEncode { path: PathBuf, source: png::EncodingError },
+    /// Writing an animated GIF failed
+    GifEncode { path: PathBuf, source: gif::EncodingError },
+    /// A pixel has a color no material stands for, with [`ColorMatch::Exact`]
+    UnknownColor { path: PathBuf, color: (u8, u8, u8), x: usize, y: usize },
+    /// The scaled image is wider or taller than the format allows, or has more
+    /// than [`MAX_PIXELS`] pixels
+    TooLarge { path: PathBuf, width: usize, height: usize, scale: usize, max: usize },
+}
+
+impl fmt::Display for ImageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ImageError::Io { path, source } => write!(f, "{}: {}", path.display(), source),
+            ImageError::Decode { path, source } => write!(f, "{}: {}", path.display(), source),
+            ImageError::PngEncode { path, source } => write!(f, "{}: {}", path.display(), source),
+            ImageError::GifEncode { path, source } => write!(f, "{}: {}", path.display(), source),
+            ImageError::UnknownColor { path, color: (r, g, b), x, y } => write!(
+                f,
+                "{}: pixel ({x}, {y}) has color [{r}, {g}, {b}], which is not in the material palette",
+                path.display(),
+            ),
+            ImageError::TooLarge { path, width, height, scale, max } => write!(
+                f,
+                "{}: {width}x{height} cells at scale {scale} is over the limit of {max} pixels per side or {MAX_PIXELS} in all",
+                path.display(),
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ImageError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ImageError::Io { source, .. } => Some(source),
+            ImageError::Decode { source, .. } => Some(source),
+            ImageError::PngEncode { source, .. } => Some(source),
+            ImageError::GifEncode { source, .. } => Some(source),
+            ImageError::UnknownColor { .. } | ImageError::TooLarge { .. } => None,
+        }
+    }
+}
+
+
+/// Most pixels an exported image may have, a gigabyte of RGBA
+pub const MAX_PIXELS: usize = 1 << 28;
+
+
+/// What to do with pixels whose color matches no material exactly
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ColorMatch {
+    /// Use the material with the closest color
+    Nearest,
+    /// Fail with [`ImageError::UnknownColor`]
+    Exact,
+}
+
+
+/// Pixel colors and the materials they stand for: each material's `image_color`,
+/// or its base color when it has none
+pub fn image_palette(materials: &MaterialRegistry) -> Vec<((u8, u8, u8), MaterialId)> {
+    materials
+        .iter()
+        .map(|(id, material)| (material.image_color.unwrap_or(material.color), id))
+        .collect()
+}
+
+/// The material whose palette color is closest to `color`, and whether it matched exactly
+fn nearest_material(palette: &[((u8, u8, u8), MaterialId)], color: (u8, u8, u8)) -> (MaterialId, bool) {
+    let distance = |(r, g, b): (u8, u8, u8)| {
+        let dr = r as i32 - color.0 as i32;
+        let dg = g as i32 - color.1 as i32;
+        let db = b as i32 - color.2 as i32;
+        dr * dr + dg * dg + db * db
+    };
+
+    palette
+        .iter()
+        .min_by_key(|&&(candidate, _)| distance(candidate))
+        .map_or((AIR, false), |&(candidate, id)| (id, distance(candidate) == 0))
+}
+
+
+/// Fills `world` from a PNG image, one material per pixel
+///
+/// The image is stretched to the grid with nearest-neighbor sampling when the
+/// sizes differ. Mostly transparent pixels become air
+pub fn load_png(path: &Path, world: &mut World, matching: ColorMatch) -> Result<(), ImageError> {
+    let decode_error = |source| ImageError::Decode { path: path.to_path_buf(), source };
+
+    let file = File::open(path).map_err(|source| ImageError::Io { path: path.to_path_buf(), source })?;
+    let mut decoder = png::Decoder::new(BufReader::new(file));
+    decoder.set_transformations(Transformations::EXPAND | Transformations::STRIP_16);
+    let mut reader = decoder.read_info().map_err(decode_error)?;
+    let mut buffer = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buffer).map_err(decode_error)?;
+
+    let image_w = info.width as usize;
+    let image_h = info.height as usize;
+    let channels = info.color_type.samples();
+    let rgba = |x: usize, y: usize| -> (u8, u8, u8, u8) {
+        let p = &buffer[y * info.line_size + x * channels..];
+        match info.color_type {
+            ColorType::Grayscale => (p[0], p[0], p[0], 255),
+            ColorType::GrayscaleAlpha => (p[0], p[0], p[0], p[1]),
+            ColorType::Rgb => (p[0], p[1], p[2], 255),
+            _ => (p[0], p[1], p[2], p[3]),
+        }
+    };
+
+    let palette = image_palette(world.materials());
+    let mut grid = vec![AIR; world.width() * world.height()];
+    for y in 0..world.height() {
+        for x in 0..world.width() {
+            let image_x = x * image_w / world.width();
+            let image_y = y * image_h / world.height();
+            let (r, g, b, a) = rgba(image_x, image_y);
+            if a < 128 {
+                continue;
+            }
+
+            let (material, exact) = nearest_material(&palette, (r, g, b));
+            if !exact && matching == ColorMatch::Exact {
+                return Err(ImageError::UnknownColor {
+                    path: path.to_path_buf(),
+                    color: (r, g, b),
+                    x: image_x,
+                    y: image_y,
+                });
+            }
+            grid[x + y * world.width()] = material;
+        }
+    }
+
+    // Only touch the world once the whole image is known to be usable
+    for (idx, &material) in grid.iter().enumerate() {
+        world.set(idx % world.width(), idx / world.width(), material);
+    }
+
+    Ok(())
+}
+
+
+/// Pixel size of a `width` x `height` grid drawn `scale` times larger, as long
+/// as neither side goes over `max` and the whole image stays under [`MAX_PIXELS`]
+fn scaled_size(path: &Path, width: usize, height: usize, scale: usize, max: usize) -> Result<(usize, usize), ImageError> {
+    let too_large = || ImageError::TooLarge { path: path.to_path_buf(), width, height, scale, max };
+    let scaled_w = width.checked_mul(scale).filter(|&w| w <= max).ok_or_else(too_large)?;
+    let scaled_h = height.checked_mul(scale).filter(|&h| h <= max).ok_or_else(too_large)?;
+    scaled_w.checked_mul(scaled_h).filter(|&pixels| pixels <= MAX_PIXELS).ok_or_else(too_large)?;
+    Ok((scaled_w, scaled_h))
+}
+
+/// Blows row `y` of an RGBA image `width` pixels wide up by `scale`, into `out`
+fn upscale_row(pixels: &[u8], width: usize, y: usize, scale: usize, out: &mut Vec<u8>) {
+    let row = &pixels[y * width * 4..(y + 1) * width * 4];
+    for pixel in row.chunks_exact(4) {
+        for _ in 0..scale {
+            out.extend_from_slice(pixel);
+        }
+    }
+}
+
+/// Blows an RGBA image of `width` x `height` pixels up by `scale` in both directions
+fn upscale(pixels: &[u8], width: usize, height: usize, scale: usize) -> Vec<u8> {
+    if scale <= 1 {
+        return pixels.to_vec();
+    }
+
+    let mut scaled = Vec::with_capacity(pixels.len() * scale * scale);
+    let mut row = Vec::with_capacity(width * scale * 4);
+    for y in 0..height {
+        row.clear();
+        upscale_row(pixels, width, y, scale, &mut row);
+        for _ in 0..scale {
+            scaled.extend_from_slice(&row);
+        }
+    }
+    scaled
+}
+
+
+/// Writes RGBA `pixels`, as filled by [`World::render`], to a PNG file with
+/// every cell drawn as a `scale` x `scale` square
+///
+/// Rows are scaled and written one at a time, so only the file grows with `scale`
+pub fn save_png(path: &Path, pixels: &[u8], width: usize, height: usize, scale: usize) -> Result<(), ImageError> {
+    let encode_error = |source| ImageError::PngEncode { path: path.to_path_buf(), source };
+
+    let scale = scale.max(1);
+    let (scaled_w, scaled_h) = scaled_size(path, width, height, scale, u32::MAX as usize)?;
+
+    let file = File::create(path).map_err(|source| ImageError::Io { path: path.to_path_buf(), source })?;
+    let mut encoder = png::Encoder::new(BufWriter::new(file), scaled_w as u32, scaled_h as u32);
+    encoder.set_color(ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+
+    let io_error = |source| ImageError::Io { path: path.to_path_buf(), source };
+    let mut writer = encoder.write_header().map_err(encode_error)?;
+    let mut stream = writer.stream_writer().map_err(encode_error)?;
+    let mut row = Vec::with_capacity(scaled_w * 4);
+    for y in 0..height {
+        row.clear();
+        upscale_row(pixels, width, y, scale, &mut row);
+        for _ in 0..scale {
+            stream.write_all(&row).map_err(io_error)?;
+        }
+    }
+    stream.finish().map_err(encode_error)
+}
+
+
+/// Streams rendered frames into a looping animated GIF
+pub struct GifRecorder {
+    path: PathBuf,
+    encoder: gif::Encoder<BufWriter<File>>,
+    width: usize,
+    height: usize,
+    scale: usize,
+    /// Hundredths of a second each frame stays on screen
+    delay: u16,
+    frames: usize,
+}
+
+impl GifRecorder {
+    /// Starts a recording of a `width` x `height` grid, drawn `scale` times larger,
+    /// showing each frame for `delay` hundredths of a second
+    pub fn create(path: &Path, width: usize, height: usize, scale: usize, delay: u16) -> Result<GifRecorder, ImageError> {
+        let encode_error = |source| ImageError::GifEncode { path: path.to_path_buf(), source };
+
+        let scale = scale.max(1);
+        let (scaled_w, scaled_h) = scaled_size(path, width, height, scale, u16::MAX as usize)?;
+
+        let file = File::create(path).map_err(|source| ImageError::Io { path: path.to_path_buf(), source })?;
+        let mut encoder = gif::Encoder::new(BufWriter::new(file), scaled_w as u16, scaled_h as u16, &[])
+            .map_err(encode_error)?;
+        encoder.set_repeat(gif::Repeat::Infinite).map_err(encode_error)?;
+
+        Ok(GifRecorder {
+            path: path.to_path_buf(),
+            encoder,
+            width,
+            height,
+            scale,
+            delay,
+            frames: 0,
+        })
+    }
+
+    /// Appends one frame of RGBA `pixels`, as filled by [`World::render`]
+    pub fn add_frame(&mut self, pixels: &[u8]) -> Result<(), ImageError> {
+        let mut scaled = upscale(pixels, self.width, self.height, self.scale);
+        let mut frame = gif::Frame::from_rgba_speed(
+            (self.width * self.scale) as u16,
+            (self.height * self.scale) as u16,
+            &mut scaled,
+            10,
+        );
+        frame.delay = self.delay;
+
+        self.encoder
+            .write_frame(&frame)
+            .map_err(|source| ImageError::GifEncode { path: self.path.clone(), source })?;
+        self.frames += 1;
+        Ok(())
+    }
+
+    /// Number of frames recorded so far
+    pub fn frames(&self) -> usize {
+        self.frames
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Writes the end of the GIF and closes the file
+    pub fn finish(self) -> Result<(), ImageError> {
+        let io_error = |source| ImageError::Io { path: self.path.clone(), source };
+        self.encoder.into_inner().and_then(|mut out| out.flush()).map_err(io_error)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    /// A path in the temporary directory unique to this test run
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("sandsim-{}-{name}", std::process::id()))
+    }
+
+    /// Writes a 3 x 1 image: the exact image color of `material`, a color a little
+    /// off from it, and a transparent pixel
+    fn write_image(path: &Path, world: &World, material: MaterialId) {
+        let material = world.materials().get(material);
+        let (r, g, b) = material.image_color.unwrap_or(material.color);
+        let pixels = [r, g, b, 255, r.saturating_add(3), g.saturating_sub(3), b, 255, r, g, b, 0];
+        save_png(path, &pixels, 3, 1, 1).unwrap();
+    }
+
+    #[test]
+    fn nearest_matches_every_opaque_pixel() {
+        let path = temp_path("nearest.png");
+        let mut world = World::new(3, 1, 1);
+        let sand = world.materials().id("sand").unwrap();
+        write_image(&path, &world, sand);
+
+        let result = load_png(&path, &mut world, ColorMatch::Nearest);
+        fs::remove_file(&path).unwrap();
+        result.unwrap();
+        assert_eq!([world.get(0, 0), world.get(1, 0), world.get(2, 0)], [sand, sand, AIR]);
+    }
+
+    #[test]
+    fn exact_rejects_colors_that_are_off() {
+        let path = temp_path("exact.png");
+        let mut world = World::new(3, 1, 1);
+        let sand = world.materials().id("sand").unwrap();
+        write_image(&path, &world, sand);
+
+        let result = load_png(&path, &mut world, ColorMatch::Exact);
+        fs::remove_file(&path).unwrap();
+        assert!(matches!(result, Err(ImageError::UnknownColor { x: 1, y: 0, .. })));
+        assert_eq!(world.get(0, 0), AIR, "the world is left alone on failure");
+    }
+
+    #[test]
+    fn oversized_exports_fail_without_creating_a_file() {
+        let path = temp_path("huge.png");
+        let pixels = vec![255; 80 * 60 * 4];
+        let result = save_png(&path, &pixels, 80, 60, 100_000);
+        assert!(matches!(result, Err(ImageError::TooLarge { .. })));
+        assert!(!path.exists());
+
+        let result = GifRecorder::create(&path, 7000, 100, 10, 3);
+        assert!(matches!(result, Err(ImageError::TooLarge { max: 65535, .. })));
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn scaled_exports_repeat_every_pixel() {
+        let path = temp_path("scaled.png");
+        let mut loaded = World::new(6, 3, 1);
+        let [sand, water] = ["sand", "water"].map(|name| loaded.materials().id(name).unwrap());
+        let palette = image_palette(loaded.materials());
+        let color = |id| palette.iter().find(|&&(_, material)| material == id).unwrap().0;
+        let pixels: Vec<u8> = [color(sand), color(water)].iter().flat_map(|&(r, g, b)| [r, g, b, 255]).collect();
+        save_png(&path, &pixels, 2, 1, 3).unwrap();
+
+        let result = load_png(&path, &mut loaded, ColorMatch::Nearest);
+        fs::remove_file(&path).unwrap();
+        result.unwrap();
+        let row = |y| (0..6).map(|x| loaded.get(x, y)).collect::<Vec<_>>();
+        assert_eq!(row(0), [sand, sand, sand, water, water, water]);
+        assert_eq!(row(2), row(0));
+    }
+
+    #[test]
+    fn images_are_stretched_to_the_grid() {
+        let path = temp_path("stretched.png");
+        let mut world = World::new(6, 2, 1);
+        let sand = world.materials().id("sand").unwrap();
+        write_image(&path, &world, sand);
+
+        let result = load_png(&path, &mut world, ColorMatch::Nearest);
+        fs::remove_file(&path).unwrap();
+        result.unwrap();
+        let materials: Vec<_> = (0..6).map(|x| world.get(x, 1)).collect();
+        assert_eq!(materials, [sand, sand, sand, sand, AIR, AIR]);
+    }
+}
@@ -0,0 +1,29 @@
+#[cfg(feature = "gui")]
+pub mod canvas;
+pub mod cell;
+pub mod config;
+pub mod history;
+pub mod image;
+pub mod material;
+pub mod reaction;
+pub mod replay;
+pub mod save;
+pub mod shape;
+pub mod texture;
+pub mod timeline;
+pub mod timestep;
+pub mod world;
+
+pub use cell::Cell;
+pub use config::{load_materials, ConfigError};
+pub use history::History;
+pub use image::{load_png, save_png, ColorMatch, GifRecorder, ImageError};
+pub use material::{Material, MaterialId, MaterialRegistry};
+pub use reaction::{Neighborhood, Reaction, ReactionTable};
+pub use replay::{Edit, Replay, ReplayError};
+pub use save::SaveError;
+pub use shape::{indices_in_circle, Brush, Shape};
+pub use texture::generate_ice_texture;
+pub use timeline::Timeline;
+pub use timestep::{FixedTimestep, RateCounter};
+pub use world::World;
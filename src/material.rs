@@ -0,0 +1,291 @@
+use serde::Deserialize;
+
+use crate::config::parse_materials;
+use crate::reaction::ReactionTable;
+
+
+/// Index of a material in a [`MaterialRegistry`], as stored in the cell grid
+pub type MaterialId = u8;
+
+/// Empty space; always the first entry of every registry
+pub const AIR: MaterialId = 0;
+
+/// Default temperature of new cells, in degrees
+pub const AMBIENT_TEMPERATURE: f32 = 20.0;
+
+
+/// How a material moves through the grid
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum State {
+    /// Falls and piles up by sliding diagonally (sand)
+    Powder,
+    /// Falls, slides diagonally and spreads sideways (water, lava)
+    Liquid,
+    /// Rises instead of falling
+    Gas,
+    /// Falls straight down as a single block (stone, ice)
+    Solid,
+    /// Never moves on its own (steel)
+    Static,
+}
+
+
+/// How a material is colored when drawn, on top of its base color
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Shading {
+    /// The base color, unchanged
+    Flat,
+    /// Base color darkened by the per-cell grain and a checkerboard
+    Grainy,
+    /// Base color scaled by the per-cell grain
+    Textured,
+    /// Base color darkening with depth, blended with `surface` on the top layer
+    Liquid { surface: (u8, u8, u8) },
+    /// Base color, switching to `crack` along the ice texture's cracks
+    Cracked { crack: (u8, u8, u8) },
+    /// Flickers between the base color and `tip` from tick to tick
+    Fire { tip: (u8, u8, u8) },
+    /// Base color fading to black as the cell's lifetime runs out
+    Fade,
+}
+
+
+/// Special per-tick behavior on top of the movement given by [`State`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Behavior {
+    None,
+    /// Clears every cell within `radius`, including itself
+    Explode { radius: i32 },
+    /// Moves right one cell per tick and turns into `into` when blocked
+    Fly { into: MaterialId },
+}
+
+
+/// Turns a cell into `into` once its temperature crosses `at`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Transition {
+    pub at: f32,
+    pub into: MaterialId,
+}
+
+
+/// Cells live a random number of ticks between `min` and `max`, then turn into `into`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Lifetime {
+    pub min: u16,
+    pub max: u16,
+    pub into: MaterialId,
+}
+
+
+/// How a flammable material catches fire and burns
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Burn {
+    /// Chance per tick to catch fire from each igniting neighbor, 0 to 1
+    pub chance: f32,
+    /// Ticks a cell burns for
+    pub duration: u16,
+    /// What a cell turns into once burnt out
+    pub leaves: MaterialId,
+    /// Material released into the air above a burning cell, such as fire or smoke
+    pub emits: Option<MaterialId>,
+}
+
+
+/// Everything the engine and the renderer need to know about a material
+#[derive(Clone, Debug)]
+pub struct Material {
+    pub name: String,
+    /// Base color, also used for the palette preview
+    pub color: (u8, u8, u8),
+    /// Pixel color that stands for this material in imported images, if not `color`
+    pub image_color: Option<(u8, u8, u8)>,
+    /// Heavier cells sink through lighter liquids and gases, which bubble up in turn
+    pub density: f32,
+    pub state: State,
+    pub shading: Shading,
+    pub behavior: Behavior,
+    /// Temperature of freshly placed cells, in degrees
+    pub temperature: f32,
+    /// Fraction of a temperature difference evened out with a neighbor per tick, 0 to 1
+    pub conductivity: f32,
+    /// Phase change when heated above a threshold
+    pub above: Option<Transition>,
+    /// Phase change when cooled below a threshold
+    pub below: Option<Transition>,
+    /// Limited lifespan, for short-lived materials like fire and smoke
+    pub lifetime: Option<Lifetime>,
+    /// Whether this material sets flammable neighbors alight
+    pub ignites: bool,
+    /// How this material burns, if it is flammable
+    pub burn: Option<Burn>,
+}
+
+impl Material {
+    pub fn new(name: &str, color: (u8, u8, u8), density: f32, state: State) -> Material {
+        Material {
+            name: name.to_string(),
+            color,
+            image_color: None,
+            density,
+            state,
+            shading: Shading::Flat,
+            behavior: Behavior::None,
+            temperature: AMBIENT_TEMPERATURE,
+            conductivity: 0.1,
+            above: None,
+            below: None,
+            lifetime: None,
+            ignites: false,
+            burn: None,
+        }
+    }
+
+    pub fn shading(mut self, shading: Shading) -> Material {
+        self.shading = shading;
+        self
+    }
+
+    pub fn behavior(mut self, behavior: Behavior) -> Material {
+        self.behavior = behavior;
+        self
+    }
+
+    pub fn image_color(mut self, image_color: (u8, u8, u8)) -> Material {
+        self.image_color = Some(image_color);
+        self
+    }
+
+    pub fn temperature(mut self, temperature: f32) -> Material {
+        self.temperature = temperature;
+        self
+    }
+
+    pub fn conductivity(mut self, conductivity: f32) -> Material {
+        self.conductivity = conductivity;
+        self
+    }
+
+    pub fn above(mut self, at: f32, into: MaterialId) -> Material {
+        self.above = Some(Transition { at, into });
+        self
+    }
+
+    pub fn below(mut self, at: f32, into: MaterialId) -> Material {
+        self.below = Some(Transition { at, into });
+        self
+    }
+
+    pub fn lifetime(mut self, min: u16, max: u16, into: MaterialId) -> Material {
+        self.lifetime = Some(Lifetime { min, max, into });
+        self
+    }
+
+    pub fn ignites(mut self, ignites: bool) -> Material {
+        self.ignites = ignites;
+        self
+    }
+
+    pub fn burn(mut self, burn: Burn) -> Material {
+        self.burn = Some(burn);
+        self
+    }
+}
+
+
+/// The set of materials a world is built from, indexed by [`MaterialId`],
+/// along with the reactions between them
+#[derive(Clone, Debug)]
+pub struct MaterialRegistry {
+    materials: Vec<Material>,
+    reactions: ReactionTable,
+}
+
+impl MaterialRegistry {
+    /// Creates a registry holding only air
+    pub fn empty() -> MaterialRegistry {
+        // Air carries heat away fast enough that lava crusts over and ice melts
+        // within a minute or so of play
+        MaterialRegistry {
+            materials: vec![Material::new("air", (0, 0, 0), 0.0, State::Gas).conductivity(0.2)],
+            reactions: ReactionTable::default(),
+        }
+    }
+
+    /// Adds a material and returns its id
+    pub fn add(&mut self, material: Material) -> MaterialId {
+        assert!(self.materials.len() <= MaterialId::MAX as usize, "at most 256 materials, air included, fit in a cell");
+        self.materials.push(material);
+        (self.materials.len() - 1) as MaterialId
+    }
+
+    pub fn get(&self, id: MaterialId) -> &Material {
+        &self.materials[id as usize]
+    }
+
+    /// Whether a cell of `material` moving `dy` rows may swap with a cell holding `other`
+    ///
+    /// Anything moves into air. Otherwise `other` has to be a liquid or gas, and the
+    /// mover has to be heavier going down, lighter going up, or pushing a lighter gas
+    /// aside when moving sideways. Liquids also push lighter liquids aside, so side
+    /// by side liquids sort into layers
+    pub fn can_enter(&self, material: MaterialId, other: MaterialId, dy: isize) -> bool {
+        if other == AIR {
+            return true;
+        }
+
+        let mover = self.get(material);
+        let other = self.get(other);
+        if !matches!(other.state, State::Liquid | State::Gas) {
+            return false;
+        }
+
+        match dy.signum() {
+            1 => mover.density > other.density,
+            -1 => mover.density < other.density,
+            _ => {
+                let pushes_aside = other.state == State::Gas || mover.state == State::Liquid;
+                pushes_aside && mover.density > other.density
+            }
+        }
+    }
+
+    pub fn reactions(&self) -> &ReactionTable {
+        &self.reactions
+    }
+
+    pub fn reactions_mut(&mut self) -> &mut ReactionTable {
+        &mut self.reactions
+    }
+
+    /// Looks a material up by name
+    pub fn id(&self, name: &str) -> Option<MaterialId> {
+        self.materials.iter().position(|m| m.name == name).map(|i| i as MaterialId)
+    }
+
+    /// Number of materials, including air
+    pub fn len(&self) -> usize {
+        self.materials.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.materials.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (MaterialId, &Material)> {
+        self.materials.iter().enumerate().map(|(i, m)| (i as MaterialId, m))
+    }
+
+    /// Ids of every material that can be painted, i.e. everything but air
+    pub fn palette(&self) -> Vec<MaterialId> {
+        (1..self.materials.len()).map(|i| i as MaterialId).collect()
+    }
+}
+
+impl Default for MaterialRegistry {
+    /// The built-in materials from `materials.toml`
+    fn default() -> MaterialRegistry {
+        parse_materials(include_str!("../materials.toml")).expect("built-in materials.toml is valid")
+    }
+}
@@ -0,0 +1,267 @@
+use rand::Rng;
+
+use crate::material::MaterialId;
+use crate::cell::Cell;
+
+
+/// Which surrounding cells a cell can react with
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Neighborhood {
+    /// The 4 orthogonal neighbors
+    VonNeumann,
+    /// All 8 surrounding cells, diagonals included
+    Moore,
+}
+
+impl Neighborhood {
+    /// Offsets of the neighbors, orthogonal ones first
+    pub fn offsets(self) -> &'static [(isize, isize)] {
+        const OFFSETS: [(isize, isize); 8] = [
+            (0, -1), (0, 1), (-1, 0), (1, 0),
+            (-1, -1), (1, -1), (-1, 1), (1, 1),
+        ];
+
+        match self {
+            Neighborhood::VonNeumann => &OFFSETS[..4],
+            Neighborhood::Moore => &OFFSETS,
+        }
+    }
+}
+
+
+/// When `a` and `b` touch, each tick there is a `probability` chance
+/// they turn into `a_becomes` and `b_becomes`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Reaction {
+    pub a: MaterialId,
+    pub b: MaterialId,
+    pub a_becomes: MaterialId,
+    pub b_becomes: MaterialId,
+    pub probability: f32,
+}
+
+
+/// A [`Reaction`] seen from one of its two sides
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Outcome {
+    pub becomes: MaterialId,
+    pub other_becomes: MaterialId,
+    pub probability: f32,
+}
+
+
+/// All reactions between materials, looked up by pair in either order
+#[derive(Clone, Debug)]
+pub struct ReactionTable {
+    neighborhood: Neighborhood,
+    reactions: Vec<Reaction>,
+    /// For each material, the materials it reacts with and what happens to both
+    by_material: Vec<Vec<(MaterialId, Outcome)>>,
+}
+
+impl ReactionTable {
+    pub fn new(neighborhood: Neighborhood) -> ReactionTable {
+        ReactionTable {
+            neighborhood,
+            reactions: Vec::new(),
+            by_material: Vec::new(),
+        }
+    }
+
+    pub fn neighborhood(&self) -> Neighborhood {
+        self.neighborhood
+    }
+
+    pub fn set_neighborhood(&mut self, neighborhood: Neighborhood) {
+        self.neighborhood = neighborhood;
+    }
+
+    /// Adds a reaction; fails with the existing one if the pair already reacts
+    pub fn add(&mut self, reaction: Reaction) -> Result<(), Reaction> {
+        if let Some(existing) = self.find(reaction.a, reaction.b) {
+            return Err(*existing);
+        }
+
+        self.reactions.push(reaction);
+        self.insert(reaction.a, reaction.b, Outcome {
+            becomes: reaction.a_becomes,
+            other_becomes: reaction.b_becomes,
+            probability: reaction.probability,
+        });
+        if reaction.a != reaction.b {
+            self.insert(reaction.b, reaction.a, Outcome {
+                becomes: reaction.b_becomes,
+                other_becomes: reaction.a_becomes,
+                probability: reaction.probability,
+            });
+        }
+
+        Ok(())
+    }
+
+    fn insert(&mut self, material: MaterialId, other: MaterialId, outcome: Outcome) {
+        let slot = material as usize;
+        if self.by_material.len() <= slot {
+            self.by_material.resize(slot + 1, Vec::new());
+        }
+        self.by_material[slot].push((other, outcome));
+    }
+
+    /// The reaction between `a` and `b`, in whichever order it was declared
+    pub fn find(&self, a: MaterialId, b: MaterialId) -> Option<&Reaction> {
+        self.reactions.iter().find(|r| (r.a == a && r.b == b) || (r.a == b && r.b == a))
+    }
+
+    /// What happens when `material` touches `other`, seen from `material`
+    pub fn lookup(&self, material: MaterialId, other: MaterialId) -> Option<Outcome> {
+        self.by_material
+            .get(material as usize)?
+            .iter()
+            .find(|(candidate, _)| *candidate == other)
+            .map(|&(_, outcome)| outcome)
+    }
+
+    /// Whether `material` reacts with anything at all
+    pub fn is_reactive(&self, material: MaterialId) -> bool {
+        self.by_material.get(material as usize).is_some_and(|outcomes| !outcomes.is_empty())
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Reaction> {
+        self.reactions.iter()
+    }
+
+    /// Whether the cell at (`x`, `y`) of a row-major `width` x `height` grid touches
+    /// a neighbor it reacts with, whether or not the reaction fires
+    pub fn can_react(&self, cells: &[Cell], width: usize, height: usize, x: usize, y: usize) -> bool {
+        let mat = cells[x + y * width].material;
+        self.is_reactive(mat) && self.neighborhood.offsets().iter().any(|&(dx, dy)| {
+            let nx = x as isize + dx;
+            let ny = y as isize + dy;
+            nx >= 0
+                && ny >= 0
+                && (nx as usize) < width
+                && (ny as usize) < height
+                && self.lookup(mat, cells[nx as usize + ny as usize * width].material).is_some()
+        })
+    }
+
+    /// Reacts the cell at (`x`, `y`) of a row-major `width` x `height` grid
+    /// with the first neighbor whose reaction fires
+    ///
+    /// # Returns
+    /// The index of the neighbor it reacted with, if any
+    pub fn react<R: Rng>(&self, cells: &mut [Cell], width: usize, height: usize, x: usize, y: usize, rng: &mut R) -> Option<usize> {
+        let idx = x + y * width;
+        let mat = cells[idx].material;
+        if !self.is_reactive(mat) {
+            return None;
+        }
+
+        for &(dx, dy) in self.neighborhood.offsets() {
+            let nx = x as isize + dx;
+            let ny = y as isize + dy;
+            if nx < 0 || ny < 0 || nx as usize >= width || ny as usize >= height {
+                continue;
+            }
+
+            let n_idx = nx as usize + ny as usize * width;
+            if let Some(outcome) = self.lookup(mat, cells[n_idx].material)
+                && rng.gen_bool(outcome.probability as f64) {
+                cells[idx].material = outcome.becomes;
+                cells[n_idx].material = outcome.other_becomes;
+                return Some(n_idx);
+            }
+        }
+
+        None
+    }
+}
+
+impl Default for ReactionTable {
+    fn default() -> ReactionTable {
+        ReactionTable::new(Neighborhood::VonNeumann)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use super::*;
+    use crate::material::AIR;
+
+    const WATER: MaterialId = 1;
+    const LAVA: MaterialId = 2;
+    const STEAM: MaterialId = 3;
+    const STONE: MaterialId = 4;
+
+    fn quench(probability: f32) -> Reaction {
+        Reaction { a: WATER, b: LAVA, a_becomes: STEAM, b_becomes: STONE, probability }
+    }
+
+    /// A 3x3 grid of air with `center` in the middle and `corner` in the top left
+    fn grid(center: MaterialId, corner: MaterialId) -> Vec<Cell> {
+        let mut cells = vec![Cell::default(); 9];
+        cells[4].material = center;
+        cells[0].material = corner;
+        cells
+    }
+
+    #[test]
+    fn lookup_works_from_both_sides() {
+        let mut table = ReactionTable::default();
+        table.add(quench(0.5)).unwrap();
+
+        let from_water = table.lookup(WATER, LAVA).unwrap();
+        assert_eq!((from_water.becomes, from_water.other_becomes), (STEAM, STONE));
+        let from_lava = table.lookup(LAVA, WATER).unwrap();
+        assert_eq!((from_lava.becomes, from_lava.other_becomes), (STONE, STEAM));
+        assert_eq!(from_lava.probability, 0.5);
+
+        assert!(table.lookup(WATER, STONE).is_none());
+        assert!(table.is_reactive(LAVA));
+        assert!(!table.is_reactive(STONE));
+    }
+
+    #[test]
+    fn add_rejects_a_pair_that_already_reacts() {
+        let mut table = ReactionTable::default();
+        table.add(quench(0.5)).unwrap();
+
+        let reversed = Reaction { a: LAVA, b: WATER, a_becomes: LAVA, b_becomes: STEAM, probability: 1.0 };
+        assert_eq!(table.add(reversed), Err(quench(0.5)));
+        assert_eq!(table.add(quench(1.0)), Err(quench(0.5)));
+        assert_eq!(table.iter().count(), 1);
+    }
+
+    #[test]
+    fn react_only_reaches_diagonals_with_eight_neighbors() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let mut table = ReactionTable::new(Neighborhood::VonNeumann);
+        table.add(quench(1.0)).unwrap();
+
+        let mut cells = grid(WATER, LAVA);
+        assert_eq!(table.react(&mut cells, 3, 3, 1, 1, &mut rng), None);
+        assert!(!table.can_react(&cells, 3, 3, 1, 1));
+        assert_eq!(cells[4].material, WATER);
+
+        table.set_neighborhood(Neighborhood::Moore);
+        assert!(table.can_react(&cells, 3, 3, 1, 1));
+        assert_eq!(table.react(&mut cells, 3, 3, 1, 1, &mut rng), Some(0));
+        assert_eq!((cells[4].material, cells[0].material), (STEAM, STONE));
+    }
+
+    #[test]
+    fn react_reaches_orthogonal_neighbors_either_way() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let mut table = ReactionTable::new(Neighborhood::VonNeumann);
+        table.add(quench(1.0)).unwrap();
+
+        let mut cells = grid(WATER, AIR);
+        cells[5].material = LAVA;
+        assert_eq!(table.react(&mut cells, 3, 3, 1, 1, &mut rng), Some(5));
+        assert_eq!((cells[4].material, cells[5].material), (STEAM, STONE));
+    }
+}
@@ -0,0 +1,354 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+
+use crate::image::{load_png, ColorMatch, ImageError};
+use crate::material::{MaterialId, MaterialRegistry};
+use crate::shape::{self, Shape};
+use crate::world::{World, MAX_CELLS};
+
+
+/// Format version written on the second line of every replay
+///
+/// Version 2 replaced the brush radius of edits with a shape
+const VERSION: u32 = 2;
+
+
+/// Everything that can go wrong while reading, writing or playing back a replay
+#[derive(Debug)]
+pub enum ReplayError {
+    /// The file could not be read or written
+    Io { path: PathBuf, source: io::Error },
+    /// A line of the file could not be understood
+    Invalid { path: PathBuf, line: usize, message: String },
+    /// The starting image could not be loaded
+    Image(ImageError),
+    /// An edit paints a material the current materials do not have
+    UnknownMaterial { name: String },
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReplayError::Io { path, source } => write!(f, "{}: {}", path.display(), source),
+            ReplayError::Invalid { path, line, message } => write!(f, "{}:{}: {}", path.display(), line, message),
+            ReplayError::Image(err) => write!(f, "{err}"),
+            ReplayError::UnknownMaterial { name } => write!(f, "replay paints unknown material \"{name}\""),
+        }
+    }
+}
+
+impl std::error::Error for ReplayError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ReplayError::Io { source, .. } => Some(source),
+            ReplayError::Image(err) => Some(err),
+            ReplayError::Invalid { .. } | ReplayError::UnknownMaterial { .. } => None,
+        }
+    }
+}
+
+
+/// One use of a painting tool: `material` painted in `shape` starting at (`x`, `y`)
+/// right after tick `tick`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Edit {
+    pub tick: u64,
+    pub x: usize,
+    pub y: usize,
+    pub shape: Shape,
+    pub material: MaterialId,
+}
+
+impl Edit {
+    /// The cells the edit paints in `world`
+    ///
+    /// Sprays are seeded from the world seed and the edit itself, so they hit
+    /// the same cells during play and replay
+    pub fn cells(&self, world: &World) -> Vec<(usize, usize)> {
+        let (width, height) = (world.width(), world.height());
+        let mut rng = StdRng::seed_from_u64(
+            world.seed() ^ self.tick.wrapping_mul(0x9e37_79b9_7f4a_7c15) ^ ((self.x as u64) << 32 | self.y as u64),
+        );
+
+        // Corners past the edges of the grid come from hand-edited replays, and
+        // would otherwise make lines and rectangles as large as they point
+        let clamp = |x: usize, y: usize| (x.min(width - 1), y.min(height - 1));
+
+        match self.shape {
+            Shape::Brush(brush) => brush.cells(self.x, self.y, width, height, &mut rng),
+            Shape::Line { x, y, brush } => {
+                let mut cells: Vec<_> = shape::line(clamp(self.x, self.y), clamp(x, y))
+                    .into_iter()
+                    .flat_map(|(x, y)| brush.cells(x, y, width, height, &mut rng))
+                    .collect();
+                // Neighboring dabs overlap a lot
+                cells.sort_unstable();
+                cells.dedup();
+                cells
+            }
+            Shape::Rect { x, y } => shape::rect(clamp(self.x, self.y), clamp(x, y)),
+            Shape::Fill => {
+                let target = world.get(self.x, self.y);
+                if target == self.material {
+                    return Vec::new();
+                }
+                shape::flood_fill(self.x, self.y, width, height, |x, y| world.get(x, y) == target)
+            }
+        }
+    }
+
+    /// Paints the edit into `world`, the same way during play and replay
+    pub fn apply(&self, world: &mut World) {
+        for (x, y) in self.cells(world) {
+            world.set(x, y, self.material);
+        }
+    }
+}
+
+
+/// Everything needed to rerun a session exactly: how the world started, every
+/// edit made to it, and the checksum it ended with
+///
+/// Stored as a small text file so it can be read and attached to bug reports
+#[derive(Clone, Debug, PartialEq)]
+pub struct Replay {
+    pub seed: u64,
+    pub width: usize,
+    pub height: usize,
+    /// Image the world was filled from before the first tick
+    pub image: Option<(PathBuf, ColorMatch)>,
+    /// Edits in the order they were made, with material names so they
+    /// survive reordering the materials file
+    pub edits: Vec<(Edit, String)>,
+    /// Tick the recording stopped at, and [`World::checksum`] at that point
+    pub end: Option<(u64, u64)>,
+}
+
+impl Replay {
+    /// Starts recording a world that was just created, and possibly filled from `image`
+    pub fn start(world: &World, image: Option<(PathBuf, ColorMatch)>) -> Replay {
+        Replay {
+            seed: world.seed(),
+            width: world.width(),
+            height: world.height(),
+            image,
+            edits: Vec::new(),
+            end: None,
+        }
+    }
+
+    /// Adds an edit that has been applied to `world`
+    pub fn record(&mut self, world: &World, edit: Edit) {
+        let name = world.materials().get(edit.material).name.clone();
+        self.edits.push((edit, name));
+    }
+
+    /// Stops recording, remembering how far `world` got and what it looked like
+    pub fn finish(&mut self, world: &World) {
+        self.end = Some((world.tick(), world.checksum()));
+    }
+
+    /// Recreates the recorded session with `materials`, returning the world as it
+    /// was when the recording stopped
+    pub fn play(&self, materials: MaterialRegistry) -> Result<World, ReplayError> {
+        let mut world = World::with_materials(self.width, self.height, self.seed, materials);
+        if let Some((path, matching)) = &self.image {
+            load_png(path, &mut world, *matching).map_err(ReplayError::Image)?;
+        }
+
+        for (edit, name) in &self.edits {
+            let material = world.materials()
+                .id(name)
+                .ok_or_else(|| ReplayError::UnknownMaterial { name: name.clone() })?;
+            while world.tick() < edit.tick {
+                world.step();
+            }
+            Edit { material, ..*edit }.apply(&mut world);
+        }
+
+        if let Some((end, _)) = self.end {
+            while world.tick() < end {
+                world.step();
+            }
+        }
+
+        Ok(world)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), ReplayError> {
+        let mut text = format!("# SandSim replay\nversion {VERSION}\nseed {}\nsize {} {}\n", self.seed, self.width, self.height);
+        if let Some((image, matching)) = &self.image {
+            let exact = if *matching == ColorMatch::Exact { "exact" } else { "nearest" };
+            text += &format!("image {exact} {}\n", image.display());
+        }
+        for (edit, name) in &self.edits {
+            text += &format!("edit {} {} {} {} {}\n", edit.tick, edit.x, edit.y, edit.shape, name);
+        }
+        if let Some((tick, checksum)) = self.end {
+            text += &format!("end {tick} {checksum:016x}\n");
+        }
+
+        fs::write(path, text).map_err(|source| ReplayError::Io { path: path.to_path_buf(), source })
+    }
+
+    pub fn load(path: &Path) -> Result<Replay, ReplayError> {
+        let text = fs::read_to_string(path).map_err(|source| ReplayError::Io { path: path.to_path_buf(), source })?;
+
+        let mut replay = Replay {
+            seed: 0,
+            width: 0,
+            height: 0,
+            image: None,
+            edits: Vec::new(),
+            end: None,
+        };
+
+        for (i, line) in text.lines().enumerate() {
+            let invalid = |message: String| ReplayError::Invalid { path: path.to_path_buf(), line: i + 1, message };
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            // Material names and image paths come last and may contain spaces
+            let (key, rest) = line.split_once(' ').unwrap_or((line, ""));
+            let mut fields = rest.splitn(5, ' ');
+            let mut number = |what: &str| -> Result<u64, ReplayError> {
+                let field = fields.next().unwrap_or("");
+                field.parse().map_err(|_| invalid(format!("expected {what}, found `{field}`")))
+            };
+
+            match key {
+                "version" => {
+                    let version = number("a version number")?;
+                    if version > VERSION as u64 {
+                        return Err(invalid(format!("made by a newer version (format {version}, this build reads up to {VERSION})")));
+                    }
+                }
+                "seed" => replay.seed = number("a seed")?,
+                "size" => {
+                    replay.width = number("a width")? as usize;
+                    replay.height = number("a height")? as usize;
+                    if replay.width.checked_mul(replay.height).is_none_or(|cells| cells > MAX_CELLS) {
+                        return Err(invalid(format!("{}x{} is over the limit of {MAX_CELLS} cells", replay.width, replay.height)));
+                    }
+                }
+                "image" => {
+                    let (matching, image) = rest.split_once(' ').unwrap_or((rest, ""));
+                    let matching = match matching {
+                        "exact" => ColorMatch::Exact,
+                        "nearest" => ColorMatch::Nearest,
+                        _ => return Err(invalid(format!("unknown color matching `{matching}`"))),
+                    };
+                    replay.image = Some((PathBuf::from(image), matching));
+                }
+                "edit" => {
+                    let tick = number("a tick")?;
+                    let x = number("an x position")? as usize;
+                    let y = number("a y position")? as usize;
+                    let shape = fields.next().unwrap_or("");
+                    let shape = Shape::parse(shape).ok_or_else(|| invalid(format!("expected a shape, found `{shape}`")))?;
+                    let name = fields.next().unwrap_or("").to_string();
+                    if replay.edits.last().is_some_and(|(last, _)| last.tick > tick) {
+                        return Err(invalid("edits are out of order".to_string()));
+                    }
+                    replay.edits.push((Edit { tick, x, y, shape, material: 0 }, name));
+                }
+                "end" => {
+                    let tick = number("a tick")?;
+                    let checksum = fields.next().unwrap_or("");
+                    let checksum = u64::from_str_radix(checksum, 16)
+                        .map_err(|_| invalid(format!("expected a checksum, found `{checksum}`")))?;
+                    replay.end = Some((tick, checksum));
+                }
+                _ => return Err(invalid(format!("unknown entry `{key}`"))),
+            }
+        }
+
+        if replay.width == 0 || replay.height == 0 {
+            return Err(ReplayError::Invalid {
+                path: path.to_path_buf(),
+                line: text.lines().count(),
+                message: "missing the world size".to_string(),
+            });
+        }
+
+        Ok(replay)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shape::Brush;
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("sandsim-{}-{name}", std::process::id()))
+    }
+
+    /// Loads a replay made of `text`
+    fn load(name: &str, text: &str) -> Result<Replay, ReplayError> {
+        let path = temp_path(name);
+        fs::write(&path, text).unwrap();
+        let replay = Replay::load(&path);
+        fs::remove_file(&path).unwrap();
+        replay
+    }
+
+    #[test]
+    fn saved_replays_load_unchanged() {
+        let edit = |tick, shape| Edit { tick, x: 3, y: 4, shape, material: 0 };
+        let replay = Replay {
+            seed: 42,
+            width: 80,
+            height: 60,
+            image: Some((PathBuf::from("start image.png"), ColorMatch::Exact)),
+            edits: vec![
+                (edit(0, Shape::Brush(Brush::Circle { radius: 2 })), "sand".to_string()),
+                (edit(5, Shape::Line { x: 70, y: 1, brush: Brush::Spray { radius: 4, density: 30 } }), "liquid water".to_string()),
+                (edit(5, Shape::Rect { x: 10, y: 20 }), "stone".to_string()),
+                (edit(9, Shape::Fill), "air".to_string()),
+            ],
+            end: Some((12, 0x0123_4567_89ab_cdef)),
+        };
+
+        let path = temp_path("round-trip.replay");
+        replay.save(&path).unwrap();
+        let loaded = Replay::load(&path);
+        fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.unwrap(), replay);
+    }
+
+    #[test]
+    fn oversized_worlds_are_rejected() {
+        let err = load("huge.replay", "size 4000000000 4000000000\n").unwrap_err();
+        assert!(matches!(err, ReplayError::Invalid { line: 1, .. }), "{err}");
+    }
+
+    #[test]
+    fn out_of_range_brushes_are_rejected() {
+        for shape in ["circle:100000", "square:-1", "100000", "spray:3:101", "line:1:1:circle:65"] {
+            let err = load("brush.replay", &format!("size 8 8\nedit 0 1 1 {shape} sand\n")).unwrap_err();
+            assert!(matches!(err, ReplayError::Invalid { line: 2, .. }), "{shape}: {err}");
+        }
+    }
+
+    #[test]
+    fn corners_past_the_grid_are_clamped() {
+        let world = World::new(8, 6, 0);
+        let edit = |shape| Edit { tick: 0, x: 2, y: 3, shape, material: 0 };
+
+        let rect = edit(Shape::Rect { x: usize::MAX, y: usize::MAX }).cells(&world);
+        assert_eq!(rect.len(), 6 * 3);
+        assert!(rect.iter().all(|&(x, y)| (2..8).contains(&x) && (3..6).contains(&y)));
+
+        let line = edit(Shape::Line { x: usize::MAX, y: 3, brush: Brush::Circle { radius: 0 } }).cells(&world);
+        assert_eq!(line, (2..8).map(|x| (x, 3)).collect::<Vec<_>>());
+    }
+}
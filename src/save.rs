@@ -0,0 +1,331 @@
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+
+use flate2::Compression;
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+
+use crate::cell::{Cell, MOVED};
+use crate::material::{MaterialRegistry, AIR};
+use crate::world::{World, MAX_CELLS};
+
+
+/// First bytes of every save file
+const MAGIC: &[u8; 4] = b"SSIM";
+
+/// Version written by this build; older versions are still read where they differ
+const VERSION: u16 = 1;
+
+
+/// Everything that can go wrong while saving or loading a world
+#[derive(Debug)]
+pub enum SaveError {
+    /// The file could not be read or written
+    Io { path: PathBuf, source: io::Error },
+    /// The file is not a save, is damaged, or comes from a newer version
+    Invalid { path: PathBuf, message: String },
+}
+
+impl fmt::Display for SaveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SaveError::Io { path, source } => write!(f, "{}: {}", path.display(), source),
+            SaveError::Invalid { path, message } => write!(f, "{}: {}", path.display(), message),
+        }
+    }
+}
+
+impl std::error::Error for SaveError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SaveError::Io { source, .. } => Some(source),
+            SaveError::Invalid { .. } => None,
+        }
+    }
+}
+
+
+impl World {
+    /// Writes the world to `path`
+    ///
+    /// The file starts with the magic bytes and a version number, followed by a
+    /// zlib stream holding the dimensions, the seed, the names of the materials
+    /// used and the run-length encoded cells
+    pub fn save(&self, path: &Path) -> Result<(), SaveError> {
+        let io_error = |source| SaveError::Io { path: path.to_path_buf(), source };
+
+        let file = File::create(path).map_err(io_error)?;
+        let mut out = BufWriter::new(file);
+        out.write_all(MAGIC).map_err(io_error)?;
+        out.write_all(&VERSION.to_le_bytes()).map_err(io_error)?;
+
+        let mut encoder = ZlibEncoder::new(out, Compression::default());
+        write_body(self, &mut encoder).map_err(io_error)?;
+        encoder.finish().and_then(|mut out| out.flush()).map_err(io_error)
+    }
+
+    /// Reads a world saved with [`World::save`], matching its materials to `materials` by name
+    ///
+    /// Cells of materials that `materials` does not know are loaded as air, so saves
+    /// stay readable after materials are renamed or removed. The random generator
+    /// restarts from the saved seed
+    pub fn load(path: &Path, materials: MaterialRegistry) -> Result<World, SaveError> {
+        let io_error = |source| SaveError::Io { path: path.to_path_buf(), source };
+        let invalid = |message: String| SaveError::Invalid { path: path.to_path_buf(), message };
+
+        let file = File::open(path).map_err(io_error)?;
+        let mut input = BufReader::new(file);
+
+        let mut magic = [0; 4];
+        let mut version = [0; 2];
+        if input.read_exact(&mut magic).is_err() || &magic != MAGIC {
+            return Err(invalid("not a SandSim save file".to_string()));
+        }
+        input.read_exact(&mut version).map_err(io_error)?;
+        let version = u16::from_le_bytes(version);
+        if version > VERSION {
+            return Err(invalid(format!("saved by a newer version (format {version}, this build reads up to {VERSION})")));
+        }
+
+        read_body(&mut ZlibDecoder::new(input), materials).map_err(|err| invalid(format!("damaged save: {err}")))
+    }
+}
+
+
+fn write_body(world: &World, out: &mut impl Write) -> io::Result<()> {
+    out.write_all(&(world.width() as u32).to_le_bytes())?;
+    out.write_all(&(world.height() as u32).to_le_bytes())?;
+    out.write_all(&world.seed().to_le_bytes())?;
+
+    // Material names are stored so ids can be remapped on load
+    out.write_all(&(world.materials().len() as u16).to_le_bytes())?;
+    for (_, material) in world.materials().iter() {
+        let name = material.name.as_bytes();
+        out.write_all(&[name.len().min(255) as u8])?;
+        out.write_all(&name[..name.len().min(255)])?;
+    }
+
+    write_cells(world.cells(), out)
+}
+
+fn read_body(input: &mut impl Read, materials: MaterialRegistry) -> io::Result<World> {
+    let width = u32::from_le_bytes(read_array(input)?) as usize;
+    let height = u32::from_le_bytes(read_array(input)?) as usize;
+    let seed = u64::from_le_bytes(read_array(input)?);
+    if width == 0 || height == 0 || width.checked_mul(height).is_none_or(|len| len > MAX_CELLS) {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, format!("bad dimensions {width}x{height}")));
+    }
+
+    // Saved id -> id in `materials`, air for names it does not have
+    let count = u16::from_le_bytes(read_array(input)?);
+    let mut remap = Vec::with_capacity(count as usize);
+    for _ in 0..count {
+        let [len] = read_array(input)?;
+        let mut name = vec![0; len as usize];
+        input.read_exact(&mut name)?;
+        remap.push(materials.id(&String::from_utf8_lossy(&name)).unwrap_or(AIR));
+    }
+
+    let mut world = World::with_materials(width, height, seed, materials);
+    read_cells(input, world.cells_mut())?;
+    for cell in world.cells_mut() {
+        cell.material = remap.get(cell.material as usize).copied().unwrap_or(AIR);
+    }
+
+    Ok(world)
+}
+
+
+/// Run-length encodes `cells` into a zlib stream, the same way saves store them
+pub(crate) fn compress_cells(cells: &[Cell]) -> Vec<u8> {
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::fast());
+    // Writing into memory cannot fail
+    let _ = write_cells(cells, &mut encoder);
+    encoder.finish().unwrap_or_default()
+}
+
+/// Fills `cells` from a stream written by [`compress_cells`]
+pub(crate) fn decompress_cells(bytes: &[u8], cells: &mut [Cell]) -> io::Result<()> {
+    read_cells(&mut ZlibDecoder::new(bytes), cells)
+}
+
+
+fn write_cells(cells: &[Cell], out: &mut impl Write) -> io::Result<()> {
+    let mut start = 0;
+    while start < cells.len() {
+        let cell = cells[start];
+        let run = cells[start..]
+            .iter()
+            .take(u16::MAX as usize)
+            .take_while(|&&other| other == cell)
+            .count();
+
+        out.write_all(&(run as u16).to_le_bytes())?;
+        write_cell(&cell, out)?;
+        start += run;
+    }
+
+    Ok(())
+}
+
+fn read_cells(input: &mut impl Read, cells: &mut [Cell]) -> io::Result<()> {
+    let mut filled = 0;
+    while filled < cells.len() {
+        let run = u16::from_le_bytes(read_array(input)?) as usize;
+        let cell = read_cell(input)?;
+        if run == 0 || filled + run > cells.len() {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "cell runs do not match the dimensions"));
+        }
+
+        cells[filled..filled + run].fill(cell);
+        filled += run;
+    }
+
+    Ok(())
+}
+
+
+fn write_cell(cell: &Cell, out: &mut impl Write) -> io::Result<()> {
+    out.write_all(&[cell.material, cell.flags & !MOVED, cell.color_seed])?;
+    out.write_all(&cell.life.to_le_bytes())?;
+    out.write_all(&cell.temperature.to_le_bytes())?;
+    out.write_all(&[cell.velocity.0 as u8, cell.velocity.1 as u8])
+}
+
+fn read_cell(input: &mut impl Read) -> io::Result<Cell> {
+    let [material, flags, color_seed]: [u8; 3] = read_array(input)?;
+    let life = u16::from_le_bytes(read_array(input)?);
+    let temperature = f32::from_le_bytes(read_array(input)?);
+    let [vx, vy]: [u8; 2] = read_array(input)?;
+
+    Ok(Cell {
+        material,
+        flags,
+        color_seed,
+        life,
+        temperature,
+        velocity: (vx as i8, vy as i8),
+    })
+}
+
+fn read_array<const N: usize>(input: &mut impl Read) -> io::Result<[u8; N]> {
+    let mut bytes = [0; N];
+    input.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::config::parse_materials;
+
+    /// A path in the temporary directory unique to this test run
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("sandsim-{}-{name}", std::process::id()))
+    }
+
+    /// Writes `body` as a save file with the given header, compressed like [`World::save`] does
+    fn write_raw(path: &Path, magic: &[u8], version: u16, body: &[u8]) {
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(body).unwrap();
+        let mut bytes = magic.to_vec();
+        bytes.extend_from_slice(&version.to_le_bytes());
+        bytes.extend_from_slice(&encoder.finish().unwrap());
+        fs::write(path, bytes).unwrap();
+    }
+
+    /// Loads `path` with the built-in materials and deletes it
+    fn load(path: &Path) -> Result<World, SaveError> {
+        let result = World::load(path, MaterialRegistry::default());
+        fs::remove_file(path).unwrap();
+        result
+    }
+
+    #[test]
+    fn saved_worlds_load_unchanged() {
+        let path = temp_path("roundtrip.sav");
+        let mut world = World::new(70, 40, 9);
+        let [sand, water, lava] = ["sand", "water", "lava"].map(|name| world.materials().id(name).unwrap());
+        for x in 0..70 {
+            world.set(x, 39, sand);
+            world.set(x, 10, water);
+        }
+        world.set(5, 5, lava);
+        for _ in 0..5 {
+            world.step();
+        }
+
+        world.save(&path).unwrap();
+        let loaded = load(&path).unwrap();
+        assert_eq!((loaded.width(), loaded.height(), loaded.seed()), (70, 40, 9));
+        let strip = |cells: &[Cell]| cells.iter().map(|cell| Cell { flags: cell.flags & !MOVED, ..*cell }).collect::<Vec<_>>();
+        assert_eq!(strip(loaded.cells()), strip(world.cells()));
+    }
+
+    #[test]
+    fn unknown_materials_load_as_air() {
+        let path = temp_path("unknown.sav");
+        let custom = parse_materials(r#"
+[[material]]
+name = "sand"
+color = [200, 180, 100]
+density = 1.5
+state = "powder"
+
+[[material]]
+name = "unobtainium"
+color = [255, 0, 255]
+density = 9
+state = "static"
+"#).unwrap();
+        let mut world = World::with_materials(4, 1, 1, custom);
+        world.set(0, 0, 1);
+        world.set(1, 0, 2);
+        world.save(&path).unwrap();
+
+        let loaded = load(&path).unwrap();
+        let sand = loaded.materials().id("sand").unwrap();
+        assert_eq!([loaded.get(0, 0), loaded.get(1, 0), loaded.get(2, 0)], [sand, AIR, AIR]);
+    }
+
+    #[test]
+    fn rejects_other_files() {
+        let path = temp_path("magic.sav");
+        write_raw(&path, b"PNG\0", VERSION, &[]);
+        assert!(matches!(load(&path), Err(SaveError::Invalid { message, .. }) if message == "not a SandSim save file"));
+    }
+
+    #[test]
+    fn rejects_newer_versions() {
+        let path = temp_path("version.sav");
+        write_raw(&path, MAGIC, VERSION + 1, &[]);
+        assert!(matches!(load(&path), Err(SaveError::Invalid { message, .. }) if message.contains("newer version")));
+    }
+
+    #[test]
+    fn rejects_cell_runs_that_do_not_fit() {
+        let path = temp_path("runs.sav");
+        let mut body = Vec::new();
+        body.extend_from_slice(&2u32.to_le_bytes());
+        body.extend_from_slice(&2u32.to_le_bytes());
+        body.extend_from_slice(&0u64.to_le_bytes());
+        body.extend_from_slice(&1u16.to_le_bytes());
+        body.extend_from_slice(&[3, b'a', b'i', b'r']);
+        // One run of 5 cells in a 2 x 2 grid
+        body.extend_from_slice(&5u16.to_le_bytes());
+        write_cell(&Cell::default(), &mut body).unwrap();
+        write_raw(&path, MAGIC, VERSION, &body);
+        assert!(matches!(load(&path), Err(SaveError::Invalid { message, .. }) if message.contains("do not match the dimensions")));
+    }
+
+    #[test]
+    fn rejects_missing_files() {
+        assert!(matches!(World::load(&temp_path("missing.sav"), MaterialRegistry::default()), Err(SaveError::Io { .. })));
+    }
+}
@@ -0,0 +1,361 @@
+use std::collections::VecDeque;
+use std::fmt;
+
+use rand::Rng;
+
+
+/// Returns a Vec of 1D indices inside a circle of given radius around a 1D position index
+/// `win_cell_w` is the width of the grid; cells past its left and right edges are left out
+pub fn indices_in_circle(pos_idx: usize, radius: i32, win_cell_w: usize) -> Vec<usize> {
+    let mut indices = Vec::new();
+
+    let cx = (pos_idx % win_cell_w) as i32;
+    let cy = (pos_idx / win_cell_w) as i32;
+
+    let r2 = radius * radius;
+
+    for dx in -radius..=radius {
+        for dy in -radius..=radius {
+            if dx*dx + dy*dy <= r2 {
+                let x = cx + dx;
+                let y = cy + dy;
+                if x >= 0 && y >= 0 && (x as usize) < win_cell_w {
+                    indices.push(x as usize + y as usize * win_cell_w);
+                }
+            }
+        }
+    }
+
+    indices
+}
+
+
+/// (`x`, `y`) if it lies on a `width` x `height` grid
+fn clip(x: isize, y: isize, width: usize, height: usize) -> Option<(usize, usize)> {
+    if x >= 0 && y >= 0 && (x as usize) < width && (y as usize) < height {
+        Some((x as usize, y as usize))
+    } else {
+        None
+    }
+}
+
+/// Cells of a `width` x `height` grid within `radius` of (`x`, `y`)
+pub fn circle(x: usize, y: usize, radius: i32, width: usize, height: usize) -> Vec<(usize, usize)> {
+    let r2 = radius * radius;
+    let mut cells = Vec::new();
+    for dy in -radius..=radius {
+        for dx in -radius..=radius {
+            if dx * dx + dy * dy <= r2
+                && let Some(cell) = clip(x as isize + dx as isize, y as isize + dy as isize, width, height) {
+                cells.push(cell);
+            }
+        }
+    }
+    cells
+}
+
+/// Cells of a `width` x `height` grid in the square reaching `radius` cells out from (`x`, `y`)
+pub fn square(x: usize, y: usize, radius: i32, width: usize, height: usize) -> Vec<(usize, usize)> {
+    let mut cells = Vec::new();
+    for dy in -radius..=radius {
+        for dx in -radius..=radius {
+            if let Some(cell) = clip(x as isize + dx as isize, y as isize + dy as isize, width, height) {
+                cells.push(cell);
+            }
+        }
+    }
+    cells
+}
+
+/// Cells on the straight line from `from` to `to`, both ends included, with no gaps
+/// between steps (Bresenham's algorithm)
+pub fn line(from: (usize, usize), to: (usize, usize)) -> Vec<(usize, usize)> {
+    let (mut x, mut y) = (from.0 as isize, from.1 as isize);
+    let (end_x, end_y) = (to.0 as isize, to.1 as isize);
+    let dx = (end_x - x).abs();
+    let dy = -(end_y - y).abs();
+    let step_x = if x < end_x { 1 } else { -1 };
+    let step_y = if y < end_y { 1 } else { -1 };
+    let mut error = dx + dy;
+
+    let mut cells = Vec::with_capacity(dx.max(-dy) as usize + 1);
+    loop {
+        cells.push((x as usize, y as usize));
+        if x == end_x && y == end_y {
+            return cells;
+        }
+        let doubled = error * 2;
+        if doubled >= dy {
+            error += dy;
+            x += step_x;
+        }
+        if doubled <= dx {
+            error += dx;
+            y += step_y;
+        }
+    }
+}
+
+/// Cells of the filled rectangle with opposite corners `from` and `to`
+pub fn rect(from: (usize, usize), to: (usize, usize)) -> Vec<(usize, usize)> {
+    let mut cells = Vec::new();
+    for y in from.1.min(to.1)..=from.1.max(to.1) {
+        for x in from.0.min(to.0)..=from.0.max(to.0) {
+            cells.push((x, y));
+        }
+    }
+    cells
+}
+
+/// Cells of a `width` x `height` grid reachable from (`x`, `y`) through side-by-side
+/// neighbors for which `inside` holds
+pub fn flood_fill(x: usize, y: usize, width: usize, height: usize, inside: impl Fn(usize, usize) -> bool) -> Vec<(usize, usize)> {
+    let mut cells = Vec::new();
+    if x >= width || y >= height || !inside(x, y) {
+        return cells;
+    }
+
+    let mut seen = vec![false; width * height];
+    let mut queue = VecDeque::from([(x, y)]);
+    seen[x + y * width] = true;
+    while let Some((x, y)) = queue.pop_front() {
+        cells.push((x, y));
+        for (dx, dy) in [(-1, 0), (1, 0), (0, -1), (0, 1)] {
+            if let Some((nx, ny)) = clip(x as isize + dx, y as isize + dy, width, height)
+                && !seen[nx + ny * width]
+                && inside(nx, ny) {
+                seen[nx + ny * width] = true;
+                queue.push_back((nx, ny));
+            }
+        }
+    }
+    cells
+}
+
+
+/// Largest brush radius, in cells
+pub const MAX_BRUSH_RADIUS: i32 = 64;
+
+/// The tip painting is done with: which cells around the cursor get painted
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Brush {
+    /// Every cell within `radius`; radius 0 paints a single cell
+    Circle { radius: i32 },
+    /// Every cell up to `radius` cells out horizontally and vertically
+    Square { radius: i32 },
+    /// Random cells within `radius`, `density` percent of them on average
+    Spray { radius: i32, density: u8 },
+}
+
+impl Brush {
+    pub fn radius(&self) -> i32 {
+        match *self {
+            Brush::Circle { radius } | Brush::Square { radius } | Brush::Spray { radius, .. } => radius,
+        }
+    }
+
+    /// The same brush with a different radius
+    pub fn with_radius(self, radius: i32) -> Brush {
+        match self {
+            Brush::Circle { .. } => Brush::Circle { radius },
+            Brush::Square { .. } => Brush::Square { radius },
+            Brush::Spray { density, .. } => Brush::Spray { radius, density },
+        }
+    }
+
+    /// Cells of a `width` x `height` grid the brush covers at (`x`, `y`);
+    /// `rng` picks the cells a spray hits
+    pub fn cells(&self, x: usize, y: usize, width: usize, height: usize, rng: &mut impl Rng) -> Vec<(usize, usize)> {
+        match *self {
+            Brush::Circle { radius } => circle(x, y, radius, width, height),
+            Brush::Square { radius } => square(x, y, radius, width, height),
+            Brush::Spray { radius, density } => {
+                let mut cells = circle(x, y, radius, width, height);
+                cells.retain(|_| rng.gen_range(0..100) < density);
+                cells
+            }
+        }
+    }
+
+    /// Cells of a `width` x `height` grid the brush could cover at (`x`, `y`),
+    /// which for a spray is its whole circle
+    pub fn footprint(&self, x: usize, y: usize, width: usize, height: usize) -> Vec<(usize, usize)> {
+        match *self {
+            Brush::Square { radius } => square(x, y, radius, width, height),
+            Brush::Circle { radius } | Brush::Spray { radius, .. } => circle(x, y, radius, width, height),
+        }
+    }
+
+    /// Reads a brush written by its `Display` implementation, like `circle:3` or `spray:5:30`
+    ///
+    /// Radii above [`MAX_BRUSH_RADIUS`] and densities above 100 are rejected
+    pub fn parse(text: &str) -> Option<Brush> {
+        let mut parts = text.split(':');
+        let kind = parts.next()?;
+        let radius = parse_radius(parts.next()?)?;
+        let brush = match kind {
+            "circle" => Brush::Circle { radius },
+            "square" => Brush::Square { radius },
+            "spray" => {
+                let density = parts.next()?.parse().ok().filter(|&density| density <= 100)?;
+                Brush::Spray { radius, density }
+            }
+            _ => return None,
+        };
+        parts.next().is_none().then_some(brush)
+    }
+}
+
+/// A brush radius from `0` to [`MAX_BRUSH_RADIUS`]
+fn parse_radius(text: &str) -> Option<i32> {
+    text.parse().ok().filter(|radius| (0..=MAX_BRUSH_RADIUS).contains(radius))
+}
+
+impl fmt::Display for Brush {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Brush::Circle { radius } => write!(f, "circle:{radius}"),
+            Brush::Square { radius } => write!(f, "square:{radius}"),
+            Brush::Spray { radius, density } => write!(f, "spray:{radius}:{density}"),
+        }
+    }
+}
+
+
+/// Everything a single edit can paint, starting from the cell it was made at
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Shape {
+    /// One dab of the brush
+    Brush(Brush),
+    /// The brush dragged along a straight line to (`x`, `y`)
+    Line { x: usize, y: usize, brush: Brush },
+    /// A filled rectangle with its opposite corner at (`x`, `y`)
+    Rect { x: usize, y: usize },
+    /// The area of connected cells of the same material
+    Fill,
+}
+
+impl Shape {
+    /// Reads a shape written by its `Display` implementation, like `line:10:4:circle:2`
+    ///
+    /// A bare number is a circle brush of that radius, as written by replays
+    /// from before there were other shapes
+    pub fn parse(text: &str) -> Option<Shape> {
+        if text.parse::<i64>().is_ok() {
+            return parse_radius(text).map(|radius| Shape::Brush(Brush::Circle { radius }));
+        }
+
+        let (kind, rest) = text.split_once(':').unwrap_or((text, ""));
+        let mut corner = rest.splitn(3, ':');
+        let mut coordinate = || corner.next()?.parse().ok();
+        match kind {
+            "line" => {
+                let (x, y) = (coordinate()?, coordinate()?);
+                Some(Shape::Line { x, y, brush: Brush::parse(corner.next()?)? })
+            }
+            "rect" => {
+                let (x, y) = (coordinate()?, coordinate()?);
+                corner.next().is_none().then_some(Shape::Rect { x, y })
+            }
+            "fill" if rest.is_empty() => Some(Shape::Fill),
+            _ => Brush::parse(text).map(Shape::Brush),
+        }
+    }
+}
+
+impl fmt::Display for Shape {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Shape::Brush(brush) => write!(f, "{brush}"),
+            Shape::Line { x, y, brush } => write!(f, "line:{x}:{y}:{brush}"),
+            Shape::Rect { x, y } => write!(f, "rect:{x}:{y}"),
+            Shape::Fill => write!(f, "fill"),
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lines_include_both_ends_and_leave_no_gaps() {
+        for (from, to) in [((0, 0), (9, 3)), ((9, 3), (0, 0)), ((2, 8), (5, 0)), ((4, 4), (4, 4)), ((0, 5), (7, 5))] {
+            let cells = line(from, to);
+            assert_eq!(cells.first(), Some(&from));
+            assert_eq!(cells.last(), Some(&to));
+            for pair in cells.windows(2) {
+                let (a, b) = (pair[0], pair[1]);
+                assert!(a.0.abs_diff(b.0) <= 1 && a.1.abs_diff(b.1) <= 1 && a != b, "{from:?} -> {to:?}: {a:?} then {b:?}");
+            }
+            let longest = from.0.abs_diff(to.0).max(from.1.abs_diff(to.1));
+            assert_eq!(cells.len(), longest + 1, "{from:?} -> {to:?}");
+        }
+    }
+
+    #[test]
+    fn brushes_are_clipped_at_the_edges() {
+        assert_eq!(square(0, 0, 1, 10, 10), vec![(0, 0), (1, 0), (0, 1), (1, 1)]);
+        assert_eq!(square(9, 9, 1, 10, 10).len(), 4);
+        assert_eq!(square(5, 5, 1, 10, 10).len(), 9);
+
+        assert_eq!(circle(0, 0, 1, 10, 10), vec![(0, 0), (1, 0), (0, 1)]);
+        assert_eq!(circle(5, 5, 1, 10, 10).len(), 5);
+        assert_eq!(circle(5, 5, 0, 10, 10), vec![(5, 5)]);
+        assert!(circle(4, 4, 3, 5, 5).iter().all(|&(x, y)| x < 5 && y < 5));
+    }
+
+    #[test]
+    fn flood_fill_stays_inside_its_region() {
+        // A wall down column 3 splits the grid in two
+        let (width, height) = (7, 4);
+        let open = |x: usize, _: usize| x != 3;
+
+        let mut left = flood_fill(0, 0, width, height, open);
+        left.sort_unstable();
+        let mut expected: Vec<_> = (0..3).flat_map(|x| (0..height).map(move |y| (x, y))).collect();
+        expected.sort_unstable();
+        assert_eq!(left, expected);
+
+        assert_eq!(flood_fill(6, 3, width, height, open).len(), 3 * height);
+        assert!(flood_fill(3, 0, width, height, open).is_empty());
+        assert!(flood_fill(width, 0, width, height, open).is_empty());
+    }
+
+    #[test]
+    fn malformed_brushes_are_rejected() {
+        let malformed = [
+            "", "circle", "circle:", "circle:x", "circle:2:3", "spray:2", "spray:2:x", "spray:2:30:1",
+            "star:2", "circle:-1", "circle:65", "spray:2:101",
+        ];
+        for text in malformed {
+            assert_eq!(Brush::parse(text), None, "{text}");
+        }
+        assert_eq!(Brush::parse("spray:2:30"), Some(Brush::Spray { radius: 2, density: 30 }));
+
+        for text in ["line:1:2", "line:1:2:square", "rect:1", "rect:1:2:3", "fill:1"] {
+            assert_eq!(Shape::parse(text), None, "{text}");
+        }
+    }
+
+    #[test]
+    fn shapes_parse_back_from_their_text() {
+        let brushes = [
+            Brush::Circle { radius: 0 },
+            Brush::Square { radius: MAX_BRUSH_RADIUS },
+            Brush::Spray { radius: 5, density: 100 },
+        ];
+        for brush in brushes {
+            for shape in [Shape::Brush(brush), Shape::Line { x: 10, y: 4, brush }, Shape::Rect { x: 7, y: 0 }, Shape::Fill] {
+                assert_eq!(Shape::parse(&shape.to_string()), Some(shape), "{shape}");
+            }
+        }
+    }
+
+    #[test]
+    fn bare_numbers_are_circle_brushes() {
+        assert_eq!(Shape::parse("3"), Some(Shape::Brush(Brush::Circle { radius: 3 })));
+        assert_eq!(Shape::parse("-3"), None);
+    }
+}
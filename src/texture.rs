@@ -0,0 +1,91 @@
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+
+
+/// Generates a procedural ice texture using Voronoi noise for "shattered" effect
+///
+/// # Arguments
+/// * `width` - width of the texture in tiles
+/// * `height` - height of the texture in tiles
+/// * `seed` - RNG seed for reproducibility
+/// * `cell_count` - number of Voronoi cells (more cells = more cracks)
+///
+/// # Returns
+/// A 2D vector of u8 brightness values (0–255)
+pub fn generate_ice_texture(
+    width: usize,
+    height: usize,
+    seed: u64,
+    cell_count: usize,
+) -> Vec<Vec<u8>> {
+    let mut rng = StdRng::seed_from_u64(seed);
+
+    // Generate Voronoi cell centers
+    let mut centers: Vec<(f64, f64)> = Vec::new();
+    for _ in 0..cell_count {
+        let cx = rng.gen_range(0.0..width as f64);
+        let cy = rng.gen_range(0.0..height as f64);
+        centers.push((cx, cy));
+    }
+
+    // Centers are sorted into square buckets of about one center each, so only
+    // the few buckets around a tile have to be searched for its nearest two
+    let bucket_size = ((width * height) as f64 / cell_count.max(1) as f64).sqrt().max(1.0);
+    let buckets_x = (width as f64 / bucket_size).ceil().max(1.0) as usize;
+    let buckets_y = (height as f64 / bucket_size).ceil().max(1.0) as usize;
+    let mut buckets: Vec<Vec<(f64, f64)>> = vec![Vec::new(); buckets_x * buckets_y];
+    for &(cx, cy) in &centers {
+        let bx = ((cx / bucket_size) as usize).min(buckets_x - 1);
+        let by = ((cy / bucket_size) as usize).min(buckets_y - 1);
+        buckets[bx + by * buckets_x].push((cx, cy));
+    }
+
+    // Create texture
+    let mut texture: Vec<Vec<u8>> = vec![vec![180; height]; width]; // base ice brightness
+
+    for (x, column) in texture.iter_mut().enumerate() {
+        for (y, tile) in column.iter_mut().enumerate() {
+            let bx = ((x as f64 / bucket_size) as usize).min(buckets_x - 1);
+            let by = ((y as f64 / bucket_size) as usize).min(buckets_y - 1);
+
+            // Squared distances to the nearest and second nearest centers
+            let (mut d1, mut d2) = (f64::INFINITY, f64::INFINITY);
+            for ring in 0.. {
+                for ny in by.saturating_sub(ring)..=(by + ring).min(buckets_y - 1) {
+                    for nx in bx.saturating_sub(ring)..=(bx + ring).min(buckets_x - 1) {
+                        // Only the outline of the ring, the inside was searched already
+                        if nx.abs_diff(bx) != ring && ny.abs_diff(by) != ring {
+                            continue;
+                        }
+                        for &(cx, cy) in &buckets[nx + ny * buckets_x] {
+                            let dx = cx - x as f64;
+                            let dy = cy - y as f64;
+                            let distance = dx*dx + dy*dy; // squared distance
+                            if distance < d1 {
+                                (d1, d2) = (distance, d1);
+                            } else if distance < d2 {
+                                d2 = distance;
+                            }
+                        }
+                    }
+                }
+
+                // Every center further out is at least `ring` buckets away
+                let searched_all = ring >= bx.max(buckets_x - 1 - bx).max(by).max(buckets_y - 1 - by);
+                let beyond = ring as f64 * bucket_size;
+                if searched_all || beyond * beyond >= d2 {
+                    break;
+                }
+            }
+
+            // Edge detection: if close to cell boundary, make it bright (crack)
+            let edge_strength = ((d2 - d1) * 4.0).min(255.0); // scale difference
+            let edge_brightness = if edge_strength < 20.0 { 255 } else { 180 + (rng.gen_range(0.0..30.0) as u8) };
+
+            *tile = edge_brightness;
+        }
+    }
+
+    texture
+}
+
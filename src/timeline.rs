@@ -0,0 +1,290 @@
+use std::collections::VecDeque;
+
+use rand::rngs::StdRng;
+
+use crate::cell::{Cell, MOVED};
+use crate::save::{compress_cells, decompress_cells};
+use crate::world::World;
+
+
+/// Temperatures are only tracked to within this many degrees between keyframes,
+/// since heat spreading changes almost every cell a little every tick
+const TEMPERATURE_STEP: f32 = 0.5;
+
+
+/// The cells of the world after one tick
+enum Frame {
+    /// Every cell, compressed
+    Keyframe(Vec<u8>),
+    /// Cells that changed since the previous frame
+    Delta(Vec<(u32, Cell)>),
+}
+
+impl Frame {
+    /// Rough number of bytes the frame keeps alive
+    fn size(&self) -> usize {
+        match self {
+            Frame::Keyframe(bytes) => bytes.len(),
+            Frame::Delta(cells) => cells.len() * std::mem::size_of::<(u32, Cell)>(),
+        }
+    }
+}
+
+
+/// A ring buffer of the last ticks of a world, to scrub back through how it got
+/// where it is and carry on from any of them
+///
+/// Every few ticks the whole grid is stored compressed, and only the cells that
+/// changed in between
+pub struct Timeline {
+    /// Oldest first, one per tick, always starting with a keyframe
+    frames: VecDeque<Frame>,
+    /// The world's random generator after each frame's tick, so carrying on
+    /// from a frame makes the same state changes as the first time
+    rngs: VecDeque<StdRng>,
+    /// Tick of the oldest frame
+    first_tick: u64,
+    /// Most ticks kept
+    capacity: usize,
+    keyframe_interval: u64,
+    /// Cells as of the newest frame, to find what the next tick changed
+    latest: Vec<Cell>,
+}
+
+impl Timeline {
+    /// An empty timeline keeping the last `capacity` ticks, with a full copy of
+    /// the grid every `keyframe_interval` ticks
+    pub fn new(capacity: usize, keyframe_interval: u64) -> Timeline {
+        Timeline {
+            frames: VecDeque::new(),
+            rngs: VecDeque::new(),
+            first_tick: 0,
+            capacity: capacity.max(1),
+            keyframe_interval: keyframe_interval.max(1),
+            latest: Vec::new(),
+        }
+    }
+
+    /// Adds the current state of `world`; call it after every tick
+    ///
+    /// A world that did not carry on from the newest frame, because it was
+    /// loaded, resized or rewound some other way, starts the timeline over
+    pub fn record(&mut self, world: &World) {
+        let cells = world.cells();
+        if self.newest_tick() != Some(world.tick().wrapping_sub(1)) || cells.len() != self.latest.len() {
+            self.clear();
+            self.first_tick = world.tick();
+        }
+
+        let frame = if (world.tick() - self.first_tick).is_multiple_of(self.keyframe_interval) {
+            self.latest.clear();
+            self.latest.extend(cells.iter().map(|cell| Cell { flags: cell.flags & !MOVED, ..*cell }));
+            Frame::Keyframe(compress_cells(&self.latest))
+        } else {
+            let mut changed = Vec::new();
+            for (idx, (cell, old)) in cells.iter().zip(self.latest.iter_mut()).enumerate() {
+                let cell = Cell { flags: cell.flags & !MOVED, ..*cell };
+                let same = Cell { temperature: old.temperature, ..cell } == *old
+                    && (cell.temperature - old.temperature).abs() < TEMPERATURE_STEP;
+                if !same {
+                    *old = cell;
+                    changed.push((idx as u32, cell));
+                }
+            }
+            Frame::Delta(changed)
+        };
+        self.frames.push_back(frame);
+        self.rngs.push_back(world.rng().clone());
+
+        // Whole keyframe groups are dropped at once, since deltas mean nothing without one
+        while self.frames.len() > self.capacity {
+            self.frames.pop_front();
+            self.rngs.pop_front();
+            self.first_tick += 1;
+            while let Some(Frame::Delta(_)) = self.frames.front() {
+                self.frames.pop_front();
+                self.rngs.pop_front();
+                self.first_tick += 1;
+            }
+        }
+    }
+
+    /// Tick of the oldest frame kept
+    pub fn oldest_tick(&self) -> Option<u64> {
+        (!self.frames.is_empty()).then_some(self.first_tick)
+    }
+
+    /// Tick of the newest frame kept
+    pub fn newest_tick(&self) -> Option<u64> {
+        (!self.frames.is_empty()).then(|| self.first_tick + self.frames.len() as u64 - 1)
+    }
+
+    /// Rough number of bytes the frames take up
+    pub fn size(&self) -> usize {
+        self.frames.iter().map(Frame::size).sum::<usize>() + self.rngs.len() * std::mem::size_of::<StdRng>()
+    }
+
+    /// Puts the cells, tick and random generator of `world` back to how they
+    /// were at `tick`; returns false when that tick is not kept
+    pub fn seek(&self, world: &mut World, tick: u64) -> bool {
+        match self.cells_at(tick) {
+            Some(cells) if cells.len() == world.cells().len() => {
+                world.cells_mut().copy_from_slice(&cells);
+                world.set_tick(tick);
+                world.set_rng(self.rngs[(tick - self.first_tick) as usize].clone());
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Forgets every frame after `tick`, so recording carries on from there
+    /// instead of from the newest frame
+    pub fn branch(&mut self, tick: u64) {
+        let Some(cells) = self.cells_at(tick) else {
+            return;
+        };
+        self.frames.truncate((tick - self.first_tick + 1) as usize);
+        self.rngs.truncate(self.frames.len());
+        self.latest = cells;
+    }
+
+    pub fn clear(&mut self) {
+        self.frames.clear();
+        self.rngs.clear();
+        self.latest.clear();
+    }
+
+    /// The cells at `tick`, rebuilt from the keyframe before it and the deltas since
+    fn cells_at(&self, tick: u64) -> Option<Vec<Cell>> {
+        let offset = tick.checked_sub(self.first_tick)? as usize;
+        if offset >= self.frames.len() {
+            return None;
+        }
+
+        let keyframe = (0..=offset).rev().find(|&i| matches!(self.frames[i], Frame::Keyframe(_)))?;
+        let mut cells = vec![Cell::default(); self.latest.len()];
+        for frame in self.frames.range(keyframe..=offset) {
+            match frame {
+                Frame::Keyframe(bytes) => decompress_cells(bytes, &mut cells).ok()?,
+                Frame::Delta(changed) => {
+                    for &(idx, cell) in changed {
+                        cells[idx as usize] = cell;
+                    }
+                }
+            }
+        }
+        Some(cells)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A pool of water over lava, boiling into steam whose lifetimes are drawn
+    /// from the world's random generator
+    fn boiling_world() -> World {
+        let mut world = World::new(24, 24, 5);
+        let [water, lava] = ["water", "lava"].map(|name| world.materials().id(name).expect("built-in material"));
+        for x in 4..20 {
+            for y in 12..18 {
+                world.set(x, y, water);
+            }
+            for y in 18..24 {
+                world.set(x, y, lava);
+            }
+        }
+        world
+    }
+
+    /// Runs `world` for `ticks` ticks, recording each into `timeline`, and returns
+    /// the cells after every tick
+    fn run(world: &mut World, timeline: &mut Timeline, ticks: usize) -> Vec<Vec<Cell>> {
+        (0..ticks)
+            .map(|_| {
+                world.step();
+                timeline.record(world);
+                world.cells().to_vec()
+            })
+            .collect()
+    }
+
+    fn without_moved(cells: &[Cell]) -> Vec<Cell> {
+        cells.iter().map(|cell| Cell { flags: cell.flags & !MOVED, ..*cell }).collect()
+    }
+
+    #[test]
+    fn seeking_rebuilds_every_tick_from_its_keyframe() {
+        let mut world = boiling_world();
+        let mut timeline = Timeline::new(100, 4);
+        let history = run(&mut world, &mut timeline, 30);
+        let first = timeline.oldest_tick().unwrap();
+
+        for (i, expected) in history.iter().enumerate() {
+            let tick = first + i as u64;
+            let mut rebuilt = world.clone();
+            assert!(timeline.seek(&mut rebuilt, tick));
+            assert_eq!(rebuilt.tick(), tick);
+            for (cell, expected) in rebuilt.cells().iter().zip(expected) {
+                assert_eq!(cell.material, expected.material, "tick {tick}");
+                assert!(
+                    (cell.temperature - expected.temperature).abs() < TEMPERATURE_STEP,
+                    "tick {tick}: {} drifted from {}", cell.temperature, expected.temperature,
+                );
+            }
+            if i % 4 == 0 {
+                assert_eq!(rebuilt.cells(), without_moved(expected), "keyframe at tick {tick} is exact");
+            }
+        }
+
+        assert!(!timeline.seek(&mut world, first + 30));
+        assert!(!timeline.seek(&mut world, first.wrapping_sub(1)));
+    }
+
+    #[test]
+    fn whole_keyframe_groups_are_dropped_over_capacity() {
+        let mut world = boiling_world();
+        let mut timeline = Timeline::new(10, 4);
+        run(&mut world, &mut timeline, 10);
+        let first = timeline.oldest_tick().unwrap();
+        assert_eq!(timeline.newest_tick(), Some(first + 9));
+
+        run(&mut world, &mut timeline, 1);
+        assert_eq!(timeline.oldest_tick(), Some(first + 4));
+        assert_eq!(timeline.newest_tick(), Some(first + 10));
+        assert!(timeline.seek(&mut world.clone(), first + 4));
+        assert!(!timeline.seek(&mut world.clone(), first + 3));
+    }
+
+    #[test]
+    fn branching_forgets_later_ticks() {
+        let mut world = boiling_world();
+        let mut timeline = Timeline::new(100, 4);
+        run(&mut world, &mut timeline, 20);
+        let first = timeline.oldest_tick().unwrap();
+
+        assert!(timeline.seek(&mut world, first + 9));
+        timeline.branch(first + 9);
+        assert_eq!(timeline.newest_tick(), Some(first + 9));
+
+        run(&mut world, &mut timeline, 1);
+        assert_eq!(timeline.oldest_tick(), Some(first), "carrying on keeps the earlier ticks");
+        assert_eq!(timeline.newest_tick(), Some(first + 10));
+    }
+
+    #[test]
+    fn carrying_on_from_a_keyframe_repeats_the_same_ticks() {
+        let mut world = boiling_world();
+        let mut timeline = Timeline::new(100, 4);
+        let history = run(&mut world, &mut timeline, 60);
+        let first = timeline.oldest_tick().unwrap();
+
+        assert!(timeline.seek(&mut world, first + 20));
+        while world.tick() < first + 59 {
+            world.step();
+        }
+        assert_eq!(without_moved(world.cells()), without_moved(&history[59]));
+    }
+}
@@ -0,0 +1,194 @@
+use std::time::{Duration, Instant};
+
+
+/// Most ticks run for a single frame; after a stall the simulation falls behind
+/// instead of freezing the window while it catches up
+const MAX_TICKS_PER_FRAME: u32 = 8;
+
+/// Slowest and fastest speed multiplier reachable with [`FixedTimestep::faster`] and [`FixedTimestep::slower`]
+const MIN_SPEED: f64 = 0.125;
+const MAX_SPEED: f64 = 16.0;
+
+
+/// Decides how many simulation ticks to run each frame, so the simulation runs
+/// at a steady rate whatever the frame rate is
+#[derive(Clone, Debug)]
+pub struct FixedTimestep {
+    /// Ticks per second at normal speed
+    tick_rate: f64,
+    /// Multiplier on `tick_rate`, changed with fast-forward and slow-motion
+    speed: f64,
+    paused: bool,
+    /// Time that has passed but not been simulated yet
+    accumulator: Duration,
+    /// Single steps requested while paused
+    pending_steps: u32,
+}
+
+impl FixedTimestep {
+    pub fn new(tick_rate: f64) -> FixedTimestep {
+        FixedTimestep {
+            tick_rate: tick_rate.max(1.0),
+            speed: 1.0,
+            paused: false,
+            accumulator: Duration::ZERO,
+            pending_steps: 0,
+        }
+    }
+
+    /// Number of ticks to run now that `elapsed` has passed since the last call
+    pub fn advance(&mut self, elapsed: Duration) -> u32 {
+        if self.paused {
+            return std::mem::take(&mut self.pending_steps);
+        }
+
+        let tick = self.tick_duration();
+        self.accumulator += elapsed;
+
+        let mut ticks = 0;
+        while self.accumulator >= tick && ticks < MAX_TICKS_PER_FRAME {
+            self.accumulator -= tick;
+            ticks += 1;
+        }
+        if ticks == MAX_TICKS_PER_FRAME {
+            self.accumulator = Duration::ZERO;
+        }
+        ticks
+    }
+
+    /// Real time one tick takes at the current speed
+    pub fn tick_duration(&self) -> Duration {
+        Duration::from_secs_f64(1.0 / (self.tick_rate * self.speed))
+    }
+
+    pub fn paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn toggle_pause(&mut self) {
+        self.paused = !self.paused;
+        self.accumulator = Duration::ZERO;
+        self.pending_steps = 0;
+    }
+
+    /// Runs exactly one tick on the next frame; only has an effect while paused
+    pub fn step_once(&mut self) {
+        if self.paused {
+            self.pending_steps += 1;
+        }
+    }
+
+    pub fn speed(&self) -> f64 {
+        self.speed
+    }
+
+    /// Doubles the speed, up to 16x
+    pub fn faster(&mut self) {
+        self.speed = (self.speed * 2.0).min(MAX_SPEED);
+    }
+
+    /// Halves the speed, down to 1/8x
+    pub fn slower(&mut self) {
+        self.speed = (self.speed / 2.0).max(MIN_SPEED);
+    }
+}
+
+
+/// Counts events such as ticks or frames and reports how many happened per second
+#[derive(Clone, Debug)]
+pub struct RateCounter {
+    window_start: Instant,
+    count: u32,
+    rate: f64,
+}
+
+impl RateCounter {
+    pub fn new() -> RateCounter {
+        RateCounter {
+            window_start: Instant::now(),
+            count: 0,
+            rate: 0.0,
+        }
+    }
+
+    /// Records `count` more events
+    pub fn add(&mut self, count: u32) {
+        self.count += count;
+
+        let elapsed = self.window_start.elapsed();
+        if elapsed >= Duration::from_secs(1) {
+            self.rate = self.count as f64 / elapsed.as_secs_f64();
+            self.count = 0;
+            self.window_start = Instant::now();
+        }
+    }
+
+    /// Events per second over the last full second
+    pub fn rate(&self) -> f64 {
+        self.rate
+    }
+}
+
+impl Default for RateCounter {
+    fn default() -> RateCounter {
+        RateCounter::new()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MS: Duration = Duration::from_millis(1);
+
+    #[test]
+    fn leftover_time_carries_over_to_the_next_frame() {
+        let mut timestep = FixedTimestep::new(100.0);
+        assert_eq!(timestep.advance(MS * 25), 2);
+        assert_eq!(timestep.advance(MS * 4), 0);
+        assert_eq!(timestep.advance(MS), 1);
+    }
+
+    #[test]
+    fn stalls_run_at_most_a_few_ticks_and_are_then_forgotten() {
+        let mut timestep = FixedTimestep::new(100.0);
+        assert_eq!(timestep.advance(Duration::from_secs(10)), MAX_TICKS_PER_FRAME);
+        assert_eq!(timestep.advance(MS * 5), 0);
+        assert_eq!(timestep.advance(MS * 5), 1);
+    }
+
+    #[test]
+    fn paused_timesteps_only_run_requested_steps() {
+        let mut timestep = FixedTimestep::new(100.0);
+        timestep.step_once();
+        assert_eq!(timestep.advance(MS * 30), 3, "steps only count while paused");
+
+        timestep.toggle_pause();
+        timestep.step_once();
+        timestep.step_once();
+        assert_eq!(timestep.advance(Duration::from_secs(1)), 2);
+        assert_eq!(timestep.advance(Duration::from_secs(1)), 0);
+
+        timestep.step_once();
+        timestep.toggle_pause();
+        assert_eq!(timestep.advance(MS * 5), 0, "unpausing drops pending steps and time");
+    }
+
+    #[test]
+    fn speed_stays_within_its_limits() {
+        let mut timestep = FixedTimestep::new(100.0);
+        for _ in 0..10 {
+            timestep.faster();
+        }
+        assert_eq!(timestep.speed(), MAX_SPEED);
+        for _ in 0..20 {
+            timestep.slower();
+        }
+        assert_eq!(timestep.speed(), MIN_SPEED);
+
+        timestep.faster();
+        assert_eq!(timestep.tick_duration(), MS * 40);
+        assert_eq!(timestep.advance(MS * 80), 2);
+    }
+}
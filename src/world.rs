@@ -0,0 +1,1134 @@
+use std::ops::Range;
+
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use rayon::prelude::*;
+
+use crate::cell::{Cell, BURNING, MOVED};
+use crate::material::{Behavior, MaterialId, MaterialRegistry, Shading, State, AIR, AMBIENT_TEMPERATURE};
+use crate::reaction::Neighborhood;
+use crate::shape::indices_in_circle;
+use crate::texture::generate_ice_texture;
+
+
+/// Swaps two cells and marks the one now at `to` as moved
+fn move_cell(cells: &mut [Cell], from: usize, to: usize) {
+    cells.swap(from, to);
+    cells[to].flags |= MOVED;
+}
+
+
+/// Side of the square chunks the grid is split into, so chunks far enough apart
+/// can be updated on different threads at once
+pub const CHUNK_SIZE: usize = 64;
+
+/// Farthest a cell update may change the grid from the cell itself; at half a
+/// chunk, chunks updated at the same time never touch the same cells
+pub const MAX_REACH: usize = CHUNK_SIZE / 2;
+
+/// Most cells a world read from a file or the command line may have
+pub const MAX_CELLS: usize = 1 << 28;
+
+
+/// Chunks whose cells all changed temperature by less than this in a tick have
+/// cooled down enough to sleep
+const TEMPERATURE_SETTLED: f32 = 0.01;
+
+/// Chance per tick that a burning cell releases its `emits` material into the air above it
+const EMIT_CHANCE: f64 = 0.3;
+
+/// Darkest and brightest flame color drawn over burning cells
+const FLAME_COLORS: ((u8, u8, u8), (u8, u8, u8)) = ((255, 80, 0), (255, 200, 60));
+
+
+/// Creates a fresh cell of `material` at its default temperature, with a random
+/// color seed and, for short-lived materials, a random lifetime
+fn spawn(material: MaterialId, materials: &MaterialRegistry, rng: &mut impl Rng) -> Cell {
+    let mut cell = Cell::new(material, materials.get(material).temperature, rng.r#gen());
+    transform(&mut cell, material, materials, rng);
+    cell
+}
+
+/// Turns `cell` into `material` in place, keeping its temperature and color seed
+/// but putting out any fire and restarting its lifetime
+fn transform(cell: &mut Cell, material: MaterialId, materials: &MaterialRegistry, rng: &mut impl Rng) {
+    cell.material = material;
+    cell.flags &= !BURNING;
+    cell.life = materials.get(material).lifetime.map_or(0, |lifetime| rng.gen_range(lifetime.min..=lifetime.max));
+}
+
+/// Picks a color between `low` and `high` that changes every tick as `cell.life` counts down
+fn flicker(low: [i32; 3], high: (u8, u8, u8), cell: &Cell) -> [i32; 3] {
+    let high = [high.0 as i32, high.1 as i32, high.2 as i32];
+    let t = (cell.life as i32 * 37 + cell.color_seed as i32) % 8;
+    [0, 1, 2].map(|i| low[i] + (high[i] - low[i]) * t / 7)
+}
+
+
+/// Maps a temperature to a black-body style color: blue below ambient,
+/// black at ambient, then red, yellow and white as it heats up
+pub fn heat_color(temperature: f32) -> (u8, u8, u8) {
+    if temperature < AMBIENT_TEMPERATURE {
+        let cold = ((AMBIENT_TEMPERATURE - temperature) / 60.0).min(1.0);
+        return (0, (cold * 64.0) as u8, (cold * 255.0) as u8);
+    }
+
+    let hot = ((temperature - AMBIENT_TEMPERATURE) / 1200.0).min(1.0) * 3.0;
+    let red = hot.min(1.0);
+    let green = (hot - 1.0).clamp(0.0, 1.0);
+    let blue = (hot - 2.0).clamp(0.0, 1.0);
+    ((red * 255.0) as u8, (green * 255.0) as u8, (blue * 255.0) as u8)
+}
+
+
+/// A grid of materials and everything needed to simulate and draw it,
+/// independent of any windowing library
+#[derive(Clone)]
+pub struct World {
+    width: usize,
+    height: usize,
+    materials: MaterialRegistry,
+    cells: Vec<Cell>,
+    ice_texture: Vec<Vec<u8>>,
+    seed: u64,
+    /// The only source of randomness in the simulation, so a seed replays exactly
+    rng: StdRng,
+    /// Ticks simulated since the world was created or loaded
+    tick: u64,
+    /// Per chunk, row-major: whether it is simulated next tick. Chunks where
+    /// nothing moved or changed temperature, and nothing did next to them,
+    /// sleep until woken
+    awake: Vec<bool>,
+}
+
+impl World {
+    /// Creates an empty world of `width` x `height` cells with the built-in materials
+    /// `seed` drives the procedural ice texture and every random choice the simulation makes
+    pub fn new(width: usize, height: usize, seed: u64) -> World {
+        World::with_materials(width, height, seed, MaterialRegistry::default())
+    }
+
+    /// Creates an empty world of `width` x `height` cells made of `materials`
+    pub fn with_materials(width: usize, height: usize, seed: u64, materials: MaterialRegistry) -> World {
+        let air = Cell::new(AIR, materials.get(AIR).temperature, 0);
+        World {
+            width,
+            height,
+            materials,
+            cells: vec![air; width * height],
+            ice_texture: generate_ice_texture(width, height, seed, 160),
+            seed,
+            rng: StdRng::seed_from_u64(seed),
+            tick: 0,
+            awake: vec![true; width.div_ceil(CHUNK_SIZE) * height.div_ceil(CHUNK_SIZE)],
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn materials(&self) -> &MaterialRegistry {
+        &self.materials
+    }
+
+    /// The seed the world was created with
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Changes the grid size, keeping the cells that still fit. Content stays on
+    /// the floor and against the left wall, and new space is filled with air
+    pub fn resize(&mut self, width: usize, height: usize) {
+        let air = Cell::new(AIR, self.materials.get(AIR).temperature, 0);
+        let mut cells = vec![air; width * height];
+        for y in 0..height.min(self.height) {
+            let old_row = (self.height - 1 - y) * self.width;
+            let new_row = (height - 1 - y) * width;
+            let count = width.min(self.width);
+            cells[new_row..new_row + count].copy_from_slice(&self.cells[old_row..old_row + count]);
+        }
+
+        self.width = width;
+        self.height = height;
+        self.cells = cells;
+        self.ice_texture = generate_ice_texture(width, height, self.seed, 160);
+        self.awake = vec![true; width.div_ceil(CHUNK_SIZE) * height.div_ceil(CHUNK_SIZE)];
+    }
+
+    /// Ticks simulated since the world was created or loaded
+    pub fn tick(&self) -> u64 {
+        self.tick
+    }
+
+    /// Moves the tick counter, for when the cells are put back to how they were at `tick`
+    pub(crate) fn set_tick(&mut self, tick: u64) {
+        self.tick = tick;
+    }
+
+    /// The generator painting and state changes draw from
+    pub(crate) fn rng(&self) -> &StdRng {
+        &self.rng
+    }
+
+    /// Puts the generator back to how it was, for when the cells are put back too
+    pub(crate) fn set_rng(&mut self, rng: StdRng) {
+        self.rng = rng;
+    }
+
+    /// Restarts the random generator and the ice texture from `seed`
+    pub fn set_seed(&mut self, seed: u64) {
+        self.seed = seed;
+        self.rng = StdRng::seed_from_u64(seed);
+        self.ice_texture = generate_ice_texture(self.width, self.height, seed, 160);
+    }
+
+    /// Returns the material at (`x`, `y`), or air outside the grid
+    pub fn get(&self, x: usize, y: usize) -> MaterialId {
+        if x >= self.width || y >= self.height {
+            return AIR;
+        }
+        self.cells[x + y * self.width].material
+    }
+
+    /// Places `material` at (`x`, `y`) at its default temperature;
+    /// positions outside the grid and unknown materials are ignored
+    pub fn set(&mut self, x: usize, y: usize, material: MaterialId) {
+        if x >= self.width || y >= self.height || material as usize >= self.materials.len() {
+            return;
+        }
+        self.cells[x + y * self.width] = spawn(material, &self.materials, &mut self.rng);
+        self.wake(x, y);
+    }
+
+    /// Returns the temperature at (`x`, `y`), or that of air outside the grid
+    pub fn temperature(&self, x: usize, y: usize) -> f32 {
+        if x >= self.width || y >= self.height {
+            return self.materials.get(AIR).temperature;
+        }
+        self.cells[x + y * self.width].temperature
+    }
+
+    /// Heats or cools the cell at (`x`, `y`); positions outside the grid are ignored
+    pub fn set_temperature(&mut self, x: usize, y: usize, temperature: f32) {
+        if x >= self.width || y >= self.height {
+            return;
+        }
+        self.cells[x + y * self.width].temperature = temperature;
+        self.wake(x, y);
+    }
+
+    /// The raw row-major cell grid
+    pub fn cells(&self) -> &[Cell] {
+        &self.cells
+    }
+
+    /// The raw row-major cell grid, for replacing cells wholesale; wakes every chunk
+    pub fn cells_mut(&mut self) -> &mut [Cell] {
+        self.awake.fill(true);
+        &mut self.cells
+    }
+
+    /// Number of chunks across and down
+    pub fn chunks(&self) -> (usize, usize) {
+        (self.width.div_ceil(CHUNK_SIZE), self.height.div_ceil(CHUNK_SIZE))
+    }
+
+    /// The chunks updated next tick, as `(x, y, width, height)` in cells
+    pub fn awake_chunks(&self) -> impl Iterator<Item = (usize, usize, usize, usize)> + '_ {
+        let (chunks_x, _) = self.chunks();
+        self.awake.iter().enumerate().filter(|&(_, &awake)| awake).map(move |(chunk, _)| {
+            let (xs, ys) = chunk_cells(chunk, chunks_x, self.width, self.height);
+            (xs.start, ys.start, xs.len(), ys.len())
+        })
+    }
+
+    /// Wakes the chunk holding (`x`, `y`) and the ones around it
+    fn wake(&mut self, x: usize, y: usize) {
+        let (chunks_x, chunks_y) = self.chunks();
+        wake_around(&mut self.awake, chunks_x, chunks_y, x / CHUNK_SIZE, y / CHUNK_SIZE);
+    }
+
+    /// Returns the on-screen color of the cell at (`x`, `y`)
+    pub fn cell_color(&self, x: usize, y: usize) -> (u8, u8, u8) {
+        let cell = self.cells[x + y * self.width];
+        let mat = cell.material;
+        if mat == AIR {
+            return (0, 0, 0);
+        }
+
+        let material = self.materials.get(mat);
+        let (r, g, b) = material.color;
+        let base = [r as i32, g as i32, b as i32];
+        // Grain brightness in the range of the old rock texture, 51–128
+        let rock = 51 + cell.color_seed as i32 * 77 / 255;
+
+        // Checkerboard pattern
+        let clean_random_offset = 255 - (((x + y) % 2 + 1) * 215).min(255) as i32;
+
+        let rgb = match material.shading {
+            Shading::Flat => base,
+            Shading::Grainy => base.map(|c| c - clean_random_offset - rock / 2),
+            Shading::Textured => base.map(|c| c * rock / 90),
+            Shading::Liquid { surface } => {
+                let deep = base.map(|c| (c - y as i32 - clean_random_offset / 8).max(0));
+                if y != 0 && self.get(x, y - 1) != mat {
+                    let surface = [surface.0 as i32, surface.1 as i32, surface.2 as i32];
+                    [0, 1, 2].map(|i| (deep[i] + surface[i]) / 2)
+                } else {
+                    deep
+                }
+            }
+            Shading::Cracked { crack } => {
+                if self.ice_texture[x][y] > 250 {
+                    [crack.0 as i32, crack.1 as i32, crack.2 as i32]
+                } else {
+                    base
+                }
+            }
+            Shading::Fire { tip } => flicker(base, tip, &cell),
+            Shading::Fade => {
+                let max = material.lifetime.map_or(1, |lifetime| lifetime.max.max(1)) as i32;
+                base.map(|c| c * (cell.life as i32).min(max) / max)
+            }
+        };
+
+        let rgb = if cell.burning() {
+            let (low, high) = FLAME_COLORS;
+            let flame = flicker([low.0 as i32, low.1 as i32, low.2 as i32], high, &cell);
+            [0, 1, 2].map(|i| (rgb[i] + flame[i]) / 2)
+        } else {
+            rgb
+        };
+
+        let [red, green, blue] = rgb.map(|c| c.clamp(0, 255) as u8);
+        (red, green, blue)
+    }
+
+    /// Draws the world into an RGBA8 pixel buffer with one pixel per cell
+    /// `pixels` must hold at least `width * height * 4` bytes
+    pub fn render(&self, pixels: &mut [u8]) {
+        self.render_region(pixels, 0, 0, self.width, self.height);
+    }
+
+    /// Like [`World::render`], but only draws the `width` x `height` cells at (`x`, `y`),
+    /// leaving the rest of `pixels` as it was
+    pub fn render_region(&self, pixels: &mut [u8], x: usize, y: usize, width: usize, height: usize) {
+        for y in y..(y + height).min(self.height) {
+            for x in x..(x + width).min(self.width) {
+                let (red, green, blue) = self.cell_color(x, y);
+                let p = (x + y * self.width) * 4;
+                pixels[p] = red;
+                pixels[p + 1] = green;
+                pixels[p + 2] = blue;
+                pixels[p + 3] = 255;
+            }
+        }
+    }
+
+    /// Like [`World::render`], but tints every cell by its temperature
+    pub fn render_heat(&self, pixels: &mut [u8]) {
+        self.render_heat_region(pixels, 0, 0, self.width, self.height);
+    }
+
+    /// Like [`World::render_region`], but tints every cell by its temperature
+    pub fn render_heat_region(&self, pixels: &mut [u8], x: usize, y: usize, width: usize, height: usize) {
+        for y in y..(y + height).min(self.height) {
+            for x in x..(x + width).min(self.width) {
+                let (red, green, blue) = self.cell_color(x, y);
+                let (heat_red, heat_green, heat_blue) = heat_color(self.temperature(x, y));
+                let p = (x + y * self.width) * 4;
+                pixels[p] = ((red as u16 + heat_red as u16 * 3) / 4) as u8;
+                pixels[p + 1] = ((green as u16 + heat_green as u16 * 3) / 4) as u8;
+                pixels[p + 2] = ((blue as u16 + heat_blue as u16 * 3) / 4) as u8;
+                pixels[p + 3] = 255;
+            }
+        }
+    }
+
+    /// Advances the simulation by one tick
+    ///
+    /// Only awake chunks are simulated; the ones that moved, heated up or cooled
+    /// down wake themselves and their neighbors for the next tick
+    pub fn step(&mut self) {
+        let awake = std::mem::take(&mut self.awake);
+        let mut next = vec![false; awake.len()];
+        self.update_cells(&awake, &mut next);
+        self.diffuse_heat(&awake, &mut next);
+        self.apply_transitions(&awake, &mut next);
+        self.awake = next;
+        self.tick += 1;
+    }
+
+    /// FNV-1a hash of the material grid, for checking that two runs ended up identical
+    pub fn checksum(&self) -> u64 {
+        self.cells.iter().fold(0xcbf2_9ce4_8422_2325, |hash, cell| {
+            (hash ^ cell.material as u64).wrapping_mul(0x0000_0100_0000_01b3)
+        })
+    }
+
+    /// Moves, reacts and runs the behavior of every cell once
+    ///
+    /// Chunks are updated in four phases of a checkerboard: chunks two apart in
+    /// both directions never reach the same cells, so each phase runs in parallel
+    ///
+    /// Each chunk draws from its own random stream, seeded from the world seed,
+    /// the tick and the chunk, so results do not depend on the number of threads
+    ///
+    /// Only chunks marked in `awake` are updated. Those whose cells changed or
+    /// wait on a chance that did not come up wake themselves and the chunks
+    /// around them in `next`
+    fn update_cells(&mut self, awake: &[bool], next: &mut [bool]) {
+        let (width, height) = (self.width, self.height);
+        let (chunks_x, chunks_y) = self.chunks();
+        let reach = reach(&self.materials);
+        let stream = self.seed ^ self.tick.wrapping_mul(0x9e37_79b9_7f4a_7c15);
+        let mut updated = Vec::new();
+
+        for (phase_x, phase_y) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+            let chunks: Vec<(usize, usize)> = (phase_y..chunks_y)
+                .step_by(2)
+                .flat_map(|chunk_y| (phase_x..chunks_x).step_by(2).map(move |chunk_x| (chunk_x, chunk_y)))
+                .filter(|&(chunk_x, chunk_y)| awake[chunk_x + chunk_y * chunks_x])
+                .collect();
+
+            let windows: Vec<(usize, usize, Window)> = chunks
+                .into_par_iter()
+                .map(|(chunk_x, chunk_y)| {
+                    let chunk = (chunk_x + chunk_y * chunks_x) as u64;
+                    let mut rng = StdRng::seed_from_u64(stream ^ chunk.wrapping_mul(0xbf58_476d_1ce4_e5b9));
+                    let mut window = Window::around(&self.cells, width, height, chunk_x, chunk_y, reach);
+                    window.update(&self.materials, &mut rng);
+                    (chunk_x, chunk_y, window)
+                })
+                .collect();
+
+            for (chunk_x, chunk_y, window) in windows {
+                if window.write_back(&mut self.cells, width) || window.restless {
+                    wake_around(next, chunks_x, chunks_y, chunk_x, chunk_y);
+                }
+                updated.push((window.x, window.y, window.width, window.height));
+            }
+        }
+
+        // === End-of-frame cleanup: clear moved flags ===
+        // Cells only move within the windows that were updated
+        for (x, y, window_w, window_h) in updated {
+            for row in y..y + window_h {
+                for cell in &mut self.cells[x + row * width..x + window_w + row * width] {
+                    cell.flags &= !MOVED;
+                }
+            }
+        }
+    }
+
+    /// Evens out temperature differences between orthogonal neighbors of the
+    /// chunks marked in `awake`, each pair exchanging heat at the lower of their
+    /// two conductivities. Chunks whose temperatures still change wake
+    /// themselves and the chunks around them in `next`
+    ///
+    /// Cells of sleeping chunks along the border get their side of each
+    /// exchange too, so heat is not made or lost there
+    fn diffuse_heat(&mut self, awake: &[bool], next: &mut [bool]) {
+        let (width, height) = (self.width, self.height);
+        let (chunks_x, chunks_y) = self.chunks();
+        let (cells, materials) = (&self.cells, &self.materials);
+        let chunks: Vec<usize> = (0..awake.len()).filter(|&chunk| awake[chunk]).collect();
+
+        // Chunks only read the grid, so they are independent of each other
+        let heated: Vec<_> = chunks
+            .into_par_iter()
+            .map(|chunk| {
+                let (xs, ys) = chunk_cells(chunk, chunks_x, width, height);
+                let mut temperatures = Vec::with_capacity(xs.len() * ys.len());
+                let mut changing = false;
+                // Heat given to or taken from cells of sleeping chunks next door,
+                // which do not compute their side of the exchange
+                let mut sleeping = Vec::new();
+                for y in ys.clone() {
+                    for x in xs.clone() {
+                        let cell = &cells[x + y * width];
+                        let conductivity = materials.get(cell.material).conductivity;
+
+                        let mut flow = 0.0;
+                        for &(dx, dy) in Neighborhood::VonNeumann.offsets() {
+                            let nx = x as isize + dx;
+                            let ny = y as isize + dy;
+                            if nx < 0 || ny < 0 || nx as usize >= width || ny as usize >= height {
+                                continue;
+                            }
+
+                            let (nx, ny) = (nx as usize, ny as usize);
+                            let neighbor = &cells[nx + ny * width];
+                            let n_conductivity = materials.get(neighbor.material).conductivity;
+                            let pair_flow = conductivity.min(n_conductivity) * (neighbor.temperature - cell.temperature);
+                            flow += pair_flow;
+
+                            if (!xs.contains(&nx) || !ys.contains(&ny))
+                                && !awake[nx / CHUNK_SIZE + ny / CHUNK_SIZE * chunks_x]
+                                && pair_flow != 0.0 {
+                                sleeping.push((nx + ny * width, -pair_flow * 0.25));
+                            }
+                        }
+
+                        // A quarter per neighbor keeps the exchange stable at conductivity 1
+                        let change = flow * 0.25;
+                        changing |= change.abs() >= TEMPERATURE_SETTLED;
+                        temperatures.push(cell.temperature + change);
+                    }
+                }
+                (chunk, temperatures, changing, sleeping)
+            })
+            .collect();
+
+        for (chunk, temperatures, changing, sleeping) in heated {
+            let (xs, ys) = chunk_cells(chunk, chunks_x, width, height);
+            let row_len = xs.len();
+            for (y, row) in ys.zip(temperatures.chunks(row_len)) {
+                for (cell, &temperature) in self.cells[xs.start + y * width..xs.end + y * width].iter_mut().zip(row) {
+                    cell.temperature = temperature;
+                }
+            }
+            if changing {
+                wake_around(next, chunks_x, chunks_y, chunk % chunks_x, chunk / chunks_x);
+            }
+            for (idx, change) in sleeping {
+                self.cells[idx].temperature += change;
+                if change.abs() >= TEMPERATURE_SETTLED {
+                    next[idx % width / CHUNK_SIZE + idx / width / CHUNK_SIZE * chunks_x] = true;
+                }
+            }
+        }
+    }
+
+    /// Melts, freezes, boils and condenses cells whose temperature crossed a threshold
+    /// in the chunks marked in `awake`, waking the chunks around them in `next`
+    fn apply_transitions(&mut self, awake: &[bool], next: &mut [bool]) {
+        let (chunks_x, chunks_y) = self.chunks();
+        for chunk in (0..awake.len()).filter(|&chunk| awake[chunk]) {
+            let (xs, ys) = chunk_cells(chunk, chunks_x, self.width, self.height);
+            for y in ys {
+                for cell in &mut self.cells[xs.start + y * self.width..xs.end + y * self.width] {
+                    let material = self.materials.get(cell.material);
+
+                    let into = if let Some(above) = material.above
+                        && cell.temperature > above.at {
+                        above.into
+                    } else if let Some(below) = material.below
+                        && cell.temperature < below.at {
+                        below.into
+                    } else {
+                        continue;
+                    };
+                    transform(cell, into, &self.materials, &mut self.rng);
+                    wake_around(next, chunks_x, chunks_y, chunk % chunks_x, chunk / chunks_x);
+                }
+            }
+        }
+    }
+}
+
+
+/// How far from itself a cell update can change the grid: one cell for moving
+/// and reacting, more for explosions
+fn reach(materials: &MaterialRegistry) -> usize {
+    materials
+        .iter()
+        .map(|(_, material)| match material.behavior {
+            Behavior::Explode { radius } => radius.max(1) as usize,
+            _ => 1,
+        })
+        .max()
+        .unwrap_or(1)
+        .min(MAX_REACH)
+}
+
+/// Columns and rows of a `width` x `height` grid covered by chunk number `chunk`
+/// of a grid `chunks_x` chunks across
+fn chunk_cells(chunk: usize, chunks_x: usize, width: usize, height: usize) -> (Range<usize>, Range<usize>) {
+    let left = chunk % chunks_x * CHUNK_SIZE;
+    let top = chunk / chunks_x * CHUNK_SIZE;
+    (left..(left + CHUNK_SIZE).min(width), top..(top + CHUNK_SIZE).min(height))
+}
+
+/// Wakes chunk (`chunk_x`, `chunk_y`) of a `chunks_x` x `chunks_y` grid of
+/// chunks and the eight around it
+fn wake_around(awake: &mut [bool], chunks_x: usize, chunks_y: usize, chunk_x: usize, chunk_y: usize) {
+    for y in chunk_y.saturating_sub(1)..(chunk_y + 2).min(chunks_y) {
+        for x in chunk_x.saturating_sub(1)..(chunk_x + 2).min(chunks_x) {
+            awake[x + y * chunks_x] = true;
+        }
+    }
+}
+
+
+/// A chunk and the cells around it within reach, copied out of the grid so it
+/// can be updated on its own thread
+struct Window {
+    /// Grid position of the top left cell of the window
+    x: usize,
+    y: usize,
+    width: usize,
+    height: usize,
+    cells: Vec<Cell>,
+    /// The chunk itself, in window coordinates
+    chunk_x: Range<usize>,
+    chunk_y: Range<usize>,
+    /// Whether some cell tried for something that did not happen this time,
+    /// so the chunk has to be updated again even if nothing changed
+    restless: bool,
+}
+
+impl Window {
+    /// Copies chunk (`chunk_x`, `chunk_y`) of a `width` x `height` grid and `reach` cells around it
+    fn around(cells: &[Cell], width: usize, height: usize, chunk_x: usize, chunk_y: usize, reach: usize) -> Window {
+        let left = chunk_x * CHUNK_SIZE;
+        let top = chunk_y * CHUNK_SIZE;
+        let right = (left + CHUNK_SIZE).min(width);
+        let bottom = (top + CHUNK_SIZE).min(height);
+
+        let x = left.saturating_sub(reach);
+        let y = top.saturating_sub(reach);
+        let window_w = (right + reach).min(width) - x;
+        let window_h = (bottom + reach).min(height) - y;
+
+        let mut window = Vec::with_capacity(window_w * window_h);
+        for row in y..y + window_h {
+            window.extend_from_slice(&cells[x + row * width..x + window_w + row * width]);
+        }
+
+        Window {
+            x,
+            y,
+            width: window_w,
+            height: window_h,
+            cells: window,
+            chunk_x: left - x..right - x,
+            chunk_y: top - y..bottom - y,
+            restless: false,
+        }
+    }
+
+    /// Updates every cell of the chunk; edges of the window that are not edges
+    /// of the grid are out of reach of every cell in it
+    fn update(&mut self, materials: &MaterialRegistry, rng: &mut impl Rng) {
+        let (width, height) = (self.width, self.height);
+        let cells = &mut self.cells;
+        let mut restless = false;
+
+        // Falling cells are updated bottom-up and rising ones top-down, so a whole
+        // column moves together instead of only its leading cell
+        let rises = |cell: &Cell| materials.get(cell.material).state == State::Gas;
+
+        for x in self.chunk_x.clone().rev() {
+            for y in self.chunk_y.clone().rev() {
+                if !rises(&cells[x + y * width]) {
+                    restless |= update_cell(materials, cells, width, height, x, y, rng);
+                }
+            }
+        }
+
+        for x in self.chunk_x.clone().rev() {
+            for y in self.chunk_y.clone() {
+                if rises(&cells[x + y * width]) {
+                    restless |= update_cell(materials, cells, width, height, x, y, rng);
+                }
+            }
+        }
+        self.restless = restless;
+    }
+
+    /// Copies the window back into the grid of `width` columns it came from;
+    /// returns whether any cell changed, not counting moved flags
+    fn write_back(&self, cells: &mut [Cell], width: usize) -> bool {
+        let mut changed = false;
+        for row in 0..self.height {
+            let start = self.x + (self.y + row) * width;
+            let target = &mut cells[start..start + self.width];
+            let source = &self.cells[row * self.width..(row + 1) * self.width];
+            changed = changed || target.iter().zip(source).any(|(old, new)| {
+                Cell { flags: old.flags & !MOVED, ..*old } != Cell { flags: new.flags & !MOVED, ..*new }
+            });
+            target.copy_from_slice(source);
+        }
+        changed
+    }
+}
+
+
+/// Moves, reacts and runs the behavior of the cell at (`x`, `y`)
+///
+/// # Returns
+/// Whether the cell could have ignited or reacted with a neighbor but the
+/// chance did not come up, so it may change later without anything else changing
+fn update_cell(materials: &MaterialRegistry, cells: &mut [Cell], width: usize, height: usize, x: usize, y: usize, rng: &mut impl Rng) -> bool {
+    let idx = x + y * width;
+
+    // Skip if already moved this frame
+    if cells[idx].moved() {
+        return false;
+    }
+
+    let mat = cells[idx].material;
+    if mat == AIR {
+        return false;
+    }
+    // Marked up front so a cell that changes state is not updated again by the other pass
+    cells[idx].flags |= MOVED;
+    let material = materials.get(mat);
+
+    let x_isize = x as isize;
+    let y_isize = y as isize;
+
+    let try_move = |dx: isize, dy: isize| -> Option<usize> {
+        let new_x = x_isize + dx;
+        let new_y = y_isize + dy;
+
+        if new_x < 0 || new_y < 0 {
+            return None;
+        }
+
+        let new_x = new_x as usize;
+        let new_y = new_y as usize;
+
+        if new_x < width && new_y < height {
+            Some(new_x + new_y * width)
+        } else {
+            None
+        }
+    };
+
+    // === LIFETIME ===
+    if let Some(lifetime) = material.lifetime {
+        if cells[idx].life == 0 {
+            transform(&mut cells[idx], lifetime.into, materials, rng);
+            return false;
+        }
+        cells[idx].life -= 1;
+    }
+
+    // === COMBUSTION ===
+    if cells[idx].burning()
+        && let Some(burn) = material.burn {
+        if cells[idx].life == 0 {
+            transform(&mut cells[idx], burn.leaves, materials, rng);
+            return false;
+        }
+        cells[idx].life -= 1;
+
+        if let Some(emits) = burn.emits
+            && let Some(above) = try_move(0, -1)
+            && cells[above].material == AIR
+            && rng.gen_bool(EMIT_CHANCE) {
+            cells[above] = spawn(emits, materials, rng);
+            cells[above].flags |= MOVED;
+        }
+    }
+
+    let mut restless = false;
+    if material.ignites || cells[idx].burning() {
+        for &(dx, dy) in Neighborhood::VonNeumann.offsets() {
+            if let Some(n_idx) = try_move(dx, dy)
+                && !cells[n_idx].burning()
+                && let Some(burn) = materials.get(cells[n_idx].material).burn {
+                if rng.gen_bool(burn.chance as f64) {
+                    cells[n_idx].flags |= BURNING;
+                    cells[n_idx].life = burn.duration;
+                } else {
+                    restless = true;
+                }
+            }
+        }
+    }
+
+    // === REACTIONS ===
+    // Reaction products start out fresh, at their own default temperature
+    if let Some(n_idx) = materials.reactions().react(cells, width, height, x, y, rng) {
+        for i in [idx, n_idx] {
+            cells[i] = spawn(cells[i].material, materials, rng);
+            cells[i].flags |= MOVED;
+        }
+        return false;
+    }
+    restless |= materials.reactions().can_react(cells, width, height, x, y);
+
+    match material.state {
+        // === POWDER ===
+        State::Powder if y < height - 1 => {
+            let below_idx = idx + width;
+
+            if materials.can_enter(mat, cells[below_idx].material, 1) {
+                move_cell(cells, idx, below_idx);
+            } else {
+                let mut free_spots = Vec::new();
+                for dir in [-1, 1] {
+                    if let Some(diag) = try_move(dir, 1)
+                        && materials.can_enter(mat, cells[diag].material, 1) {
+                        free_spots.push(diag);
+                    }
+                }
+
+                if let Some(&target_idx) = free_spots.choose(rng) {
+                    move_cell(cells, idx, target_idx);
+                }
+            }
+        }
+
+        // === LIQUID ===
+        State::Liquid => {
+            let directions = if rng.gen_bool(0.5) { [-1, 1] } else { [1, -1] };
+            // Lighter liquids already floating on a heavier one are left alone, so
+            // sorted layers settle instead of trading places forever
+            let floats = |side: usize| {
+                let other = materials.get(cells[side].material);
+                other.state == State::Liquid
+                    && side + width < cells.len()
+                    && materials.get(cells[side + width].material).state == State::Liquid
+                    && materials.get(cells[side + width].material).density > other.density
+            };
+
+            let mut target: Option<usize> = None;
+            if let Some(below) = try_move(0, 1)
+                && materials.can_enter(mat, cells[below].material, 1) {
+                target = Some(below);
+            }
+
+            if target.is_none() {
+                for &dir in &directions {
+                    if let Some(diag) = try_move(dir, 1)
+                        && materials.can_enter(mat, cells[diag].material, 1) {
+                        target = Some(diag);
+                        break;
+                    }
+                }
+            }
+
+            if target.is_none() {
+                let mut free_sides = Vec::new();
+                for &dir in &directions {
+                    if let Some(side) = try_move(dir, 0)
+                        && materials.can_enter(mat, cells[side].material, 0)
+                        && !floats(side) {
+                        free_sides.push(side);
+                    }
+                }
+                target = free_sides.choose(rng).copied();
+            }
+
+            if let Some(target_idx) = target {
+                move_cell(cells, idx, target_idx);
+            }
+        }
+
+        // === GAS ===
+        // Rises, drifts diagonally up when blocked, and otherwise spreads sideways
+        State::Gas => {
+            let directions = if rng.gen_bool(0.5) { [-1, 1] } else { [1, -1] };
+
+            let mut target: Option<usize> = None;
+            if let Some(above) = try_move(0, -1)
+                && materials.can_enter(mat, cells[above].material, -1) {
+                target = Some(above);
+            }
+
+            if target.is_none() {
+                for &dir in &directions {
+                    if let Some(diag) = try_move(dir, -1)
+                        && materials.can_enter(mat, cells[diag].material, -1) {
+                        target = Some(diag);
+                        break;
+                    }
+                }
+            }
+
+            if target.is_none() {
+                let mut free_sides = Vec::new();
+                for &dir in &directions {
+                    if let Some(side) = try_move(dir, 0)
+                        && materials.can_enter(mat, cells[side].material, 0) {
+                        free_sides.push(side);
+                    }
+                }
+                target = free_sides.choose(rng).copied();
+            }
+
+            if let Some(target_idx) = target {
+                move_cell(cells, idx, target_idx);
+            }
+        }
+
+        // === SOLID ===
+        State::Solid if y < height - 1 => {
+            let below = idx + width;
+            if materials.can_enter(mat, cells[below].material, 1) {
+                move_cell(cells, idx, below);
+            }
+        }
+
+        _ => {}
+    }
+
+    match material.behavior {
+        // === EXPLODE ===
+        Behavior::Explode { radius } => {
+            let air = Cell::new(AIR, materials.get(AIR).temperature, 0);
+            let damaged_cells = indices_in_circle(idx, radius, width);
+            for i in damaged_cells {
+                if i < cells.len() {
+                    cells[i] = air;
+                }
+            }
+        }
+
+        // === FLY ===
+        Behavior::Fly { into } => {
+            // Check if not at right edge
+            if x != width - 1 {
+                let right = idx + 1;
+                if cells[right].material == AIR {
+                    // Move one step right
+                    move_cell(cells, idx, right);
+                } else {
+                    // Blocked by another cell → turn into `into`
+                    transform(&mut cells[idx], into, materials, rng);
+                }
+            } else {
+                // Reached the edge → turn into `into`
+                transform(&mut cells[idx], into, materials, rng);
+            }
+        }
+
+        Behavior::None => {}
+    }
+
+    restless
+}
+
+
+#[cfg(test)]
+mod tests {
+    use rayon::ThreadPoolBuilder;
+
+    use super::*;
+    use crate::replay::Edit;
+    use crate::shape::{Brush, Shape};
+
+    /// Ids of the built-in materials named `names`
+    fn ids<const N: usize>(world: &World, names: [&str; N]) -> [MaterialId; N] {
+        names.map(|name| world.materials().id(name).expect("built-in material"))
+    }
+
+    #[test]
+    fn heat_spreads_from_hot_to_cold() {
+        let mut world = World::new(8, 8, 1);
+        let [stone] = ids(&world, ["stone"]);
+        for x in 0..8 {
+            for y in 0..8 {
+                world.set(x, y, stone);
+            }
+        }
+        world.set_temperature(3, 3, 500.0);
+        let total = |world: &World| world.cells().iter().map(|cell| cell.temperature).sum::<f32>();
+        let before = total(&world);
+
+        world.step();
+        assert!(world.temperature(3, 3) < 500.0);
+        assert!(world.temperature(4, 3) > AMBIENT_TEMPERATURE);
+        assert_eq!(world.temperature(5, 3), AMBIENT_TEMPERATURE, "heat moves one cell per tick");
+        assert!((total(&world) - before).abs() < 0.01, "heat is only moved around");
+    }
+
+    #[test]
+    fn ice_melts_and_lava_cools_past_their_thresholds() {
+        let mut world = World::new(8, 8, 1);
+        let [ice, water, lava, stone] = ids(&world, ["ice", "water", "lava", "stone"]);
+        world.set(1, 7, ice);
+        // Walled in so the lava stays put
+        world.set(5, 7, stone);
+        world.set(7, 7, stone);
+        world.set(6, 7, lava);
+        world.step();
+        assert_eq!((world.get(1, 7), world.get(6, 7)), (ice, lava), "still past their thresholds");
+
+        world.set_temperature(1, 7, 10.0);
+        world.set_temperature(6, 7, 300.0);
+        world.step();
+        assert_eq!((world.get(1, 7), world.get(6, 7)), (water, stone));
+        assert!(world.temperature(1, 7) > 0.0, "cells keep their temperature when they change");
+    }
+
+    /// Steps a world seeded with `seed` through sprays of every kind of material
+    /// and returns its checksum
+    fn simulate(seed: u64) -> u64 {
+        let mut world = World::new(140, 70, seed);
+        let names = ["sand", "water", "oil", "lava", "wood", "gunpowder", "ice", "steam"];
+        let materials = ids(&world, names);
+        for tick in 0..150 {
+            if tick % 10 == 0 {
+                let material = materials[(tick / 10) as usize % materials.len()];
+                let x = (tick as usize * 37) % 130 + 5;
+                let brush = Brush::Spray { radius: 8, density: 60 };
+                Edit { tick, x, y: 20, shape: Shape::Brush(brush), material }.apply(&mut world);
+            }
+            world.step();
+        }
+        world.checksum()
+    }
+
+    #[test]
+    fn same_seed_and_edits_give_the_same_world() {
+        let expected = simulate(42);
+        assert_eq!(simulate(42), expected);
+        assert_ne!(simulate(43), expected, "the seed should make a difference");
+    }
+
+    #[test]
+    fn thread_count_does_not_change_the_world() {
+        let expected = simulate(7);
+        for threads in [1, 3] {
+            let pool = ThreadPoolBuilder::new().num_threads(threads).build().unwrap();
+            assert_eq!(pool.install(|| simulate(7)), expected, "{threads} threads");
+        }
+    }
+
+    #[test]
+    fn settled_chunks_sleep_until_something_changes() {
+        let mut world = World::new(192, 128, 1);
+        let [sand, lava] = ids(&world, ["sand", "lava"]);
+        world.step();
+        assert_eq!(world.awake_chunks().count(), 0, "an empty world is settled");
+
+        world.set(10, 10, sand);
+        assert_eq!(world.awake_chunks().count(), 4, "painting wakes the chunks around it");
+        for _ in 0..150 {
+            world.step();
+        }
+        assert_eq!(world.get(10, 127), sand);
+        assert_eq!(world.awake_chunks().count(), 0, "sand on the floor is settled");
+
+        // Heat spreading out from lava keeps the chunks around it awake
+        world.set(150, 127, lava);
+        for _ in 0..50 {
+            world.step();
+        }
+        assert!(world.awake_chunks().any(|(x, y, _, _)| (x, y) == (128, 64)));
+        assert!(world.awake_chunks().all(|(x, _, _, _)| x >= 64));
+    }
+
+    #[test]
+    fn heat_flows_both_ways_into_sleeping_chunks() {
+        let mut world = World::new(CHUNK_SIZE * 2, CHUNK_SIZE, 2);
+        let [stone] = ids(&world, ["stone"]);
+        for (idx, cell) in world.cells_mut().iter_mut().enumerate() {
+            cell.material = stone;
+            cell.temperature = if idx % (CHUNK_SIZE * 2) < CHUNK_SIZE { 20.0 } else { 21.0 };
+        }
+        // Only the colder chunk is awake, and reads the warmer one across the border
+        world.awake = vec![true, false];
+
+        let total = |world: &World| world.cells().iter().map(|cell| cell.temperature as f64).sum::<f64>();
+        let before = total(&world);
+        world.step();
+        assert!(world.temperature(CHUNK_SIZE - 1, 10) > 20.0);
+        assert!(world.temperature(CHUNK_SIZE, 10) < 21.0, "the sleeping side gave up its heat");
+        assert!((total(&world) - before).abs() < 1e-3, "total temperature went from {before} to {}", total(&world));
+    }
+
+    #[test]
+    fn fire_spreads_and_burns_out_into_its_leftovers() {
+        let materials = crate::config::parse_materials(r#"
+[[material]]
+name = "match"
+color = [255, 0, 0]
+density = 1
+state = "static"
+ignites = true
+
+[[material]]
+name = "fuse"
+color = [200, 200, 200]
+density = 1
+state = "static"
+burn = { chance = 1, duration = 3, leaves = "ash", emits = "smoke" }
+
+[[material]]
+name = "ash"
+color = [90, 90, 90]
+density = 1
+state = "static"
+
+[[material]]
+name = "smoke"
+color = [70, 70, 70]
+density = 0.5
+state = "gas"
+"#).unwrap();
+        let mut world = World::with_materials(16, 8, 1, materials);
+        let [matchstick, fuse, ash, smoke] = ids(&world, ["match", "fuse", "ash", "smoke"]);
+        world.set(0, 7, matchstick);
+        for x in 1..16 {
+            world.set(x, 7, fuse);
+        }
+
+        world.step();
+        assert!(world.cells()[1 + 7 * 16].burning(), "the match lights the fuse next to it");
+        assert!(!world.cells()[3 + 7 * 16].burning(), "fire spreads one cell at a time");
+
+        for _ in 0..40 {
+            world.step();
+        }
+        assert!((1..16).all(|x| world.get(x, 7) == ash), "burnt out fuse leaves ash");
+        assert!(world.cells().iter().any(|cell| cell.material == smoke), "burning fuse gives off smoke");
+    }
+
+    #[test]
+    fn gas_bubbles_up_through_a_liquid() {
+        let mut world = World::new(16, 16, 1);
+        let [water, smoke] = ids(&world, ["water", "smoke"]);
+        for y in 8..16 {
+            for x in 0..16 {
+                world.set(x, y, water);
+            }
+        }
+        world.set(8, 15, smoke);
+
+        for _ in 0..20 {
+            world.step();
+        }
+        let smoke_at = world.cells().iter().position(|cell| cell.material == smoke);
+        assert!(smoke_at.is_some_and(|idx| idx / 16 < 8), "smoke still under water at {smoke_at:?}");
+        assert_eq!(world.cells().iter().filter(|cell| cell.material == water).count(), 16 * 8 - 1);
+    }
+
+    #[test]
+    fn water_dropped_on_oil_sinks_below_it() {
+        let mut world = World::new(64, 64, 1);
+        let [water, oil] = ids(&world, ["water", "oil"]);
+        for y in 44..54 {
+            for x in 27..37 {
+                world.set(x, y, water);
+                world.set(x, y + 10, oil);
+            }
+        }
+
+        for _ in 0..2000 {
+            world.step();
+        }
+
+        // Enough water to cover the floor, and no oil left under any of it
+        assert!((0..64).all(|x| world.get(x, 63) == water), "oil left on the floor");
+        for x in 0..64 {
+            let highest_water = (0..64).find(|&y| world.get(x, y) == water);
+            let lowest_oil = (0..64).rev().find(|&y| world.get(x, y) == oil);
+            if let (Some(water_y), Some(oil_y)) = (highest_water, lowest_oil) {
+                assert!(oil_y < water_y, "oil at row {oil_y} under water at row {water_y} in column {x}");
+            }
+        }
+        assert_eq!(world.cells().iter().filter(|cell| cell.material == oil).count(), 100);
+    }
+}